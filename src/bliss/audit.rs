@@ -0,0 +1,159 @@
+use std::{fmt, cmp::Reverse};
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::Serialize;
+use url::Url;
+use lemmy_api_common::lemmy_db_views_actor::structs::CommunityView;
+use crate::{lemmy::api::{Api, SEARCH_PAGE_LIMIT}, profile::{Profile, community::Community}};
+use super::SEARCH_MAX_PAGES;
+
+/// Posts fetched to find newest one, pinned posts come first in community listing and are skipped.
+const LATEST_POSTS: i64 = 20;
+
+/// Why followed community is considered dead.
+#[derive(Serialize)]
+#[serde(tag = "cause", content = "detail", rename_all = "snake_case")]
+pub enum Cause {
+    Deleted,
+    Removed,
+    /// No post since given date, or no posts at all.
+    Inactive(Option<NaiveDateTime>),
+    /// Home instance does not know the community or cannot be reached.
+    Unreachable(String),
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::Deleted => write!(f, "deleted"),
+            Cause::Removed => write!(f, "removed by moderators"),
+            Cause::Inactive(Some(date)) => write!(f, "last post on {}", date.date()),
+            Cause::Inactive(None) => write!(f, "no posts"),
+            Cause::Unreachable(err) => write!(f, "unreachable: {}", err),
+        }
+    }
+}
+
+/// Same named community that is still active.
+#[derive(Serialize)]
+pub struct Alternative {
+    pub actor: String,
+    pub subscribers: i64,
+}
+
+#[derive(Serialize)]
+pub struct Finding {
+    pub community: Community,
+    pub cause: Cause,
+    pub subscribers: Option<i64>,
+    pub alternatives: Vec<Alternative>,
+}
+
+/// Checks followed communities on their home instances, anonymously.
+pub struct Audit {
+    api: Api,
+    max_age: Duration,
+    /// Instance searched for alternatives, none if not wanted.
+    search_instance: Option<Url>,
+}
+
+impl Audit {
+    pub fn new(api: Api, max_age_days: i64, search_instance: Option<Url>) -> Self {
+        Audit {
+            api,
+            max_age: Duration::days(max_age_days),
+            search_instance,
+        }
+    }
+
+    /// Returns findings for dead communities only.
+    pub async fn run(&self, profile: &Profile) -> Vec<Finding> {
+        let communities = &profile.info.communities_follows;
+        let mut findings = Vec::new();
+        for (i, community) in communities.iter().enumerate() {
+            info!("Checking {} ({}/{})...", community.actor, i + 1, communities.len());
+            if let Some(mut finding) = self.check(community).await {
+                finding.alternatives = self.alternatives(community).await;
+                findings.push(finding);
+            }
+        }
+        findings
+    }
+
+    async fn check(&self, community: &Community) -> Option<Finding> {
+        let finding = |cause, subscribers| Some(Finding {
+            community: community.clone(),
+            cause,
+            subscribers,
+            alternatives: Vec::new(),
+        });
+        let home = community.actor.join("/").unwrap();
+        let details = match self.api.community(&home, &community.name).await {
+            Ok(details) => details.community_view,
+            Err(err) => return finding(Cause::Unreachable(err.to_string()), None),
+        };
+        let subscribers = Some(details.counts.subscribers);
+        if details.community.deleted {
+            return finding(Cause::Deleted, subscribers);
+        }
+        if details.community.removed {
+            return finding(Cause::Removed, subscribers);
+        }
+        let last_post = match self.api.latest_posts(&home, &community.name, LATEST_POSTS).await {
+            Ok(response) => response.posts
+                .iter()
+                .filter(|view| !view.post.featured_community)
+                .map(|view| view.post.published)
+                .max(),
+            Err(err) => return finding(Cause::Unreachable(err.to_string()), subscribers),
+        };
+        match last_post {
+            Some(date) if Utc::now().naive_utc() - date < self.max_age => None,
+            _ => finding(Cause::Inactive(last_post), subscribers),
+        }
+    }
+
+    /// Active communities of the same name, most subscribed first. Each candidate goes through
+    /// the same check on its home instance as followed communities do.
+    async fn alternatives(&self, community: &Community) -> Vec<Alternative> {
+        let mut alternatives = Vec::new();
+        for view in self.candidates(community).await {
+            let candidate = Community::from_actor(view.community.actor_id.clone());
+            match self.check(&candidate).await {
+                None => alternatives.push(Alternative {
+                    actor: view.community.actor_id.to_string(),
+                    subscribers: view.counts.subscribers,
+                }),
+                Some(finding) => debug!("Skipping alternative {}: {}.", candidate.actor, finding.cause),
+            }
+        }
+        alternatives.sort_by_key(|a| Reverse(a.subscribers));
+        alternatives
+    }
+
+    /// Other communities of the same name known to search instance, neither deleted nor removed.
+    async fn candidates(&self, community: &Community) -> Vec<CommunityView> {
+        let instance = match &self.search_instance {
+            Some(instance) => instance,
+            None => return Vec::new(),
+        };
+        let mut candidates = Vec::new();
+        for page in 1..=SEARCH_MAX_PAGES {
+            let response = match self.api.search_communities(instance, &community.name, page).await {
+                Ok(response) => response,
+                Err(err) => {
+                    warn!("Failed to search for alternatives of {}: {}", community.name, err);
+                    break;
+                },
+            };
+            let count = response.communities.len() as i64;
+            candidates.extend(response.communities
+                .into_iter()
+                .filter(|view| view.community.name == community.name && view.community.actor_id != community.actor)
+                .filter(|view| !view.community.deleted && !view.community.removed));
+            if count < SEARCH_PAGE_LIMIT {
+                break;
+            }
+        }
+        candidates
+    }
+}
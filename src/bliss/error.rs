@@ -1,11 +1,23 @@
 use crate::lemmy::LemmyError;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error(transparent)]
     LemmyError( #[from] LemmyError ),
     #[error(transparent)]
     IoError( #[from] std::io::Error ),
+    #[error("Config error: {0}")]
+    ConfigError(String),
+    /// Instance rejected credentials or could not be reached while logging in.
+    #[error("Failed while trying to login: {0}")]
+    LoginError(LemmyError),
+    /// Community or user could not be resolved on instance.
+    #[error("Unable to find {0}")]
+    NotFoundError(String),
+    /// Unknown push parameter or pattern.
+    #[error("Error: {0}")]
+    ParameterError(String),
     #[error("Error: {0}")]
     BlissError(String),
 }
@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+use lemmy_api_common::{site::GetFederatedInstancesResponse, lemmy_db_schema::{newtypes::DbUrl, source::instance::Instance}};
+use super::report::SkipReason;
+
+/// Instances the destination refuses to federate with.
+#[derive(Default)]
+pub struct Federation {
+    local: String,
+    blocked: HashSet<String>,
+    allowed: HashSet<String>,
+}
+
+impl Federation {
+    pub fn new(local: &str, response: &GetFederatedInstancesResponse) -> Self {
+        let domains = |instances: &[Instance]| instances
+            .iter()
+            .map(|instance| instance.domain.to_lowercase())
+            .collect();
+        match &response.federated_instances {
+            Some(instances) => Federation {
+                local: local.to_lowercase(),
+                blocked: domains(&instances.blocked),
+                allowed: domains(&instances.allowed),
+            },
+            None => Federation::default(),
+        }
+    }
+
+    /// Reason why object with given actor cannot be reached from destination, if there is one.
+    pub fn check(&self, actor: &DbUrl) -> Option<SkipReason> {
+        let host = actor.host_str()?.to_lowercase();
+        if host == self.local {
+            None
+        } else if self.blocked.contains(&host) {
+            Some(SkipReason::Defederated(host))
+        } else if !self.allowed.is_empty() && !self.allowed.contains(&host) {
+            Some(SkipReason::NotAllowed(host))
+        } else {
+            None
+        }
+    }
+}
@@ -1,37 +1,145 @@
 pub mod error;
 pub mod util;
+pub mod session;
+pub mod report;
+pub mod sync;
+pub mod federation;
+pub mod audit;
+pub mod progress;
 
-use std::{time::Duration, thread, cell::Cell};
-use lemmy_api_common::lemmy_db_schema::newtypes::{CommunityId, PersonId};
+use std::{time::Duration, cell::{Cell, RefCell}};
+use lemmy_api_common::{lemmy_db_schema::{SubscribedType, newtypes::{CommunityId, PersonId, DbUrl}}, person::SaveUserSettings};
 use url::Url;
-use crate::{lemmy::api::Api, user::{User, Authorized, NotAuthorized}, profile::{Profile, local_profile::LocalProfile, community::Community, person::Person, Info}, bliss::util::instance_host, log_res};
-use self::error::Error;
+use crate::{lemmy::{api::{Api, SEARCH_PAGE_LIMIT}, LemmyError}, user::{User, Authorized, NotAuthorized}, profile::{Profile, local_profile::LocalProfile, community::Community, person::Person, Info, parameters::Parameters}, bliss::util::{instance_host, get_password, Origin, PasswordSource}};
+use self::{error::Error, report::{Report, PullSummary, Action, SkipReason}, federation::Federation, progress::{Event, Callback}};
 
+/// Search pages looked through before giving up on finding community or person.
+const SEARCH_MAX_PAGES: i64 = 10;
+
+/// Session of single Lemmy account, pulling it to and pushing it from local profile.
 pub struct Bliss {
     user: User<Authorized>,
     api: Api,
     profile_name: String,
     subtractive: Cell<bool>,
+    suggest: Cell<bool>,
+    verify_delay: Cell<Duration>,
+    report: RefCell<Report>,
+    progress: Option<Callback>,
 }
 
 impl Bliss {
     pub async fn new(user: User<NotAuthorized>, password: String, token: Option<String>, profile_name: &str) -> Result<Bliss, Error> {
-        let api = Api::new();
+        Self::new_with(Api::new(), user, password, token, profile_name).await
+    }
+
+    /// Logs in through given client, e.g. one built from `ClientConfig`.
+    /// Session is kept in memory only, see `remember_session`.
+    pub async fn new_with(api: Api, user: User<NotAuthorized>, password: String, token: Option<String>, profile_name: &str) -> Result<Bliss, Error> {
         let user = api.login(user, password, token)
             .await
-            .map_err(|err| Error::BlissError(format!("Failed while trying to login: {}", err)))?;
-        let bliss = Bliss {
+            .map_err(Error::LoginError)?;
+        Ok(Bliss::with_user(user, api, profile_name))
+    }
+
+    /// Caches login token on disk, so that `resume` can reuse it in later runs.
+    pub fn remember_session(&self) -> Result<(), Error> {
+        Ok(session::store(&self.user)?)
+    }
+
+    /// Reuses cached session of account if there is one and it is still valid.
+    pub async fn resume(user: User<NotAuthorized>, profile_name: &str) -> Result<Option<Bliss>, Error> {
+        Self::resume_with(Api::new(), user, profile_name).await
+    }
+
+    pub async fn resume_with(api: Api, user: User<NotAuthorized>, profile_name: &str) -> Result<Option<Bliss>, Error> {
+        let jwt = match session::load(&user)? {
+            Some(jwt) => jwt,
+            None => return Ok(None),
+        };
+        let user = user.authorize(jwt);
+        match api.site(&user).await {
+            Ok(site) if site.my_user.is_some() => {
+                info!("Using cached session of {}@{}.", user.username, instance_host(&user.instance));
+                Ok(Some(Bliss::with_user(user, api, profile_name)))
+            },
+            Ok(_) => {
+                info!("Cached session of {}@{} expired.", user.username, instance_host(&user.instance));
+                session::remove(&user)?;
+                Ok(None)
+            },
+            Err(LemmyError::StatusError(status)) if status.is_client_error() => {
+                info!("Cached session of {}@{} rejected with {}.", user.username, instance_host(&user.instance), status);
+                session::remove(&user)?;
+                Ok(None)
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Uses cached session when valid, otherwise logs in with password from given source and caches new session.
+    pub async fn connect(api: Api, user: User<NotAuthorized>, password: Option<&PasswordSource>, origin: Origin, token: Option<String>, profile_name: &str) -> Result<Bliss, Error> {
+        if let Some(bliss) = Self::resume_with(api.clone(), user.clone(), profile_name).await? {
+            return Ok(bliss);
+        }
+        let pw = get_password(origin, password)?;
+        let bliss = Self::new_with(api, user, pw, token, profile_name).await?;
+        if let Err(err) = bliss.remember_session() {
+            warn!("Failed to cache session: {}", err);
+        }
+        Ok(bliss)
+    }
+
+    fn with_user(user: User<Authorized>, api: Api, profile_name: &str) -> Bliss {
+        let account = format!("{}@{}", user.username, instance_host(&user.instance));
+        Bliss {
             user,
             api,
             profile_name: profile_name.to_owned(),
             subtractive: Cell::new(false),
-        };
-        Ok(bliss)
+            suggest: Cell::new(false),
+            verify_delay: Cell::new(Duration::ZERO),
+            report: RefCell::new(Report::new(account)),
+            progress: None,
+        }
     }
 
-    pub async fn pull(&self) -> Result<(), Error> {
+    /// Whether to look for same named communities when followed or blocked one is unreachable from this account.
+    pub fn suggest_alternatives(&self, suggest: bool) {
+        self.suggest.set(suggest);
+    }
+
+    /// Callback receiving progress of pull, and of follows and blocks while pushing.
+    pub fn on_progress(&mut self, callback: impl Fn(&Event) + 'static) {
+        self.progress = Some(Box::new(callback));
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(callback) = &self.progress {
+            callback(&event);
+        }
+    }
+
+    /// Pending follows are checked again after given delay, zero disables it.
+    pub fn verify_subscriptions_after(&self, delay: Duration) {
+        self.verify_delay.set(delay);
+    }
+
+    pub fn account(&self) -> String {
+        format!("{}@{}", self.user.username, instance_host(&self.user.instance))
+    }
+
+    /// Lemmy RSS feed of subscribed front page, contains user token.
+    pub fn front_page_feed(&self) -> Url {
+        self.user.instance
+            .join(&format!("/feeds/front/{}.xml", self.user.token()))
+            .unwrap()
+    }
+
+    pub async fn pull(&self) -> Result<PullSummary, Error> {
         info!("Pulling {}@{} to local profile {}.",
                 self.user.username, instance_host(&self.user.instance), self.profile_name);
+        self.emit(Event::Started { total: 3, interval: Duration::ZERO });
         let site = self.api.site(&self.user).await?;
         let profile = Profile::new(self.user.clone(), &site);
         let person = &site.my_user.clone().unwrap().local_user_view.person;
@@ -43,50 +151,76 @@ impl Bliss {
         );
         lp.save()?;
         info!("Successfully saved user profile.");
-        if lp.save_avatar(avatar)? {
-            info!("Successfully saved avatar.");
-        }
-        if lp.save_banner(banner)? {
-            info!("Successfully saved banner.");
-        }
+        self.emit(Event::Applied { action: Action::Pull, target: "profile" });
+        let avatar = match lp.save_avatar(avatar)? {
+            true => {
+                info!("Successfully saved avatar.");
+                Some(lp.avatar_path()?)
+            },
+            false => None,
+        };
+        self.emit(Event::Applied { action: Action::Pull, target: "avatar" });
+        let banner = match lp.save_banner(banner)? {
+            true => {
+                info!("Successfully saved banner.");
+                Some(lp.banner_path()?)
+            },
+            false => None,
+        };
+        self.emit(Event::Applied { action: Action::Pull, target: "banner" });
+        self.emit(Event::Finished);
         info!("Pulled successfully.");
-        Ok(())
+        Ok(PullSummary {
+            account: self.account(),
+            profile_name: self.profile_name.clone(),
+            profile: lp.profile_path()?,
+            avatar,
+            banner,
+            follows: lp.profile.info.communities_follows.len(),
+            community_blocks: lp.profile.info.communities_blocks.len(),
+            person_blocks: lp.profile.info.people_blocks.len(),
+        })
     }
 
-    pub async fn push(&self, subtractive: bool, exclude: &[String], include: &[String]) -> Result<(), Error> {
+    /// Returns report of all follow and block actions taken.
+    pub async fn push(&self, subtractive: bool, parameters: &Parameters) -> Result<Report, Error> {
         info!("Pushing {}@{} from local profile {}",
             self.user.username, instance_host(&self.user.instance), self.profile_name);
-        self.subtractive.set(subtractive);
         let profile = LocalProfile::load(&self.profile_name)?;
-        let profile = self.tweak_profile(profile, exclude, include).await?;
-        self.push_settings(profile.clone()).await?;
-        self.push_info(&profile.info).await?;
+        let profile = self.tweak_profile(profile, parameters).await?;
+        self.push_profile(profile, subtractive, parameters).await
+    }
+
+    /// Current state of account.
+    pub async fn fetch_profile(&self) -> Result<Profile, Error> {
+        let site = self.api.site(&self.user).await?;
+        Ok(Profile::new(self.user.clone(), &site))
+    }
+
+    /// Pushes given profile without images, leaving excluded parameters untouched.
+    pub async fn push_profile(&self, profile: Profile, subtractive: bool, parameters: &Parameters) -> Result<Report, Error> {
+        self.subtractive.set(subtractive);
+        let excluded: Vec<_> = parameters.excluded().copied().collect();
+        if !excluded.is_empty() {
+            info!("Ignoring parameters: {}.", excluded.join(", "));
+        }
+        if !parameters.excludes_settings() {
+            self.push_settings(profile.clone(), parameters).await?;
+        }
+        self.push_info(&profile.info, parameters).await?;
         info!("Pushed successfully.");
-        Ok(())
+        let account = self.report.borrow().account.clone();
+        Ok(self.report.replace(Report::new(account)))
     }
 
-    async fn tweak_profile(&self, mut local_profile: LocalProfile, exclude: &[String], include: &[String]) -> Result<Profile, Error> {
-        local_profile.profile = local_profile
-            .profile
-            .ignore_parameters(&exclude);
-        for param in include.iter() {
-            match param.as_str() {
-                "avatar" => {
-                    let url = self.push_avatar(&local_profile).await?;
-                    local_profile.profile.info.avatar = match url {
-                        Some(url) => Some(url.to_string()),
-                        None => None,
-                    };
-                },
-                "banner" => {
-                    let url = self.push_banner(&local_profile).await?;
-                    local_profile.profile.info.banner = match url {
-                        Some(url) => Some(url.to_string()),
-                        None => None,
-                    };
-                },
-                _ => warn!("No parameter {} found.", param),
-            }
+    async fn tweak_profile(&self, mut local_profile: LocalProfile, parameters: &Parameters) -> Result<Profile, Error> {
+        if parameters.includes("avatar") {
+            let url = self.push_avatar(&local_profile).await?;
+            local_profile.profile.info.avatar = url.map(|url| url.to_string());
+        }
+        if parameters.includes("banner") {
+            let url = self.push_banner(&local_profile).await?;
+            local_profile.profile.info.banner = url.map(|url| url.to_string());
         }
         Ok(local_profile.profile)
     }
@@ -122,15 +256,17 @@ impl Bliss {
         }
     }
 
-    async fn push_settings(&self, profile: Profile) -> Result<(), Error> {
+    async fn push_settings(&self, profile: Profile, parameters: &Parameters) -> Result<(), Error> {
         info!("Uploading settings...");
-        self.api.save_user_settings(&self.user, profile)
+        let mut settings = SaveUserSettings::from(profile);
+        parameters.apply(&mut settings);
+        self.api.save_user_settings(&self.user, settings)
             .await?;
         info!("Successfully uploaded settings.");
         Ok(())
     }
 
-    async fn push_info(&self, info: &Info) -> Result<(), Error> {
+    async fn push_info(&self, info: &Info, parameters: &Parameters) -> Result<(), Error> {
         let site = self.api.site(&self.user).await?;
         let dst_profile = Profile::new(self.user.clone(), &site);
         let dst_info = dst_profile.info;
@@ -138,68 +274,190 @@ impl Bliss {
             .site_view
             .local_site_rate_limit
             .message_per_second;
-        let sleep_time = Duration::from_millis((1000 as f64 / rate_limit as f64).ceil() as u64);
-        self.push_communities(info, &dst_info, sleep_time).await;
-        self.push_users(info, &dst_info, sleep_time).await;
-        if self.subtractive.get() {
-            let undo_info = dst_info.subtract(info);
-            self.subtractive_push_info(&undo_info, sleep_time).await;
+        let sleep_time = Duration::from_millis((1000_f64 / rate_limit as f64).ceil() as u64);
+        let federation = self.federation().await;
+        let info = parameters.filter().apply(info);
+        let undo_info = match self.subtractive.get() {
+            true => Some(parameters.filter().apply(&dst_info.subtract(&info))),
+            false => None,
+        };
+        let total = Self::count(&info.subtract(&dst_info), parameters)
+            + undo_info.as_ref().map_or(0, |undo_info| Self::count(undo_info, parameters));
+        self.emit(Event::Started { total, interval: sleep_time });
+        self.push_communities(&info, &dst_info, parameters, &federation, sleep_time).await;
+        self.push_users(&info, &dst_info, parameters, &federation, sleep_time).await;
+        self.verify_subscriptions().await;
+        if let Some(undo_info) = undo_info {
+            self.subtractive_push_info(&undo_info, parameters, sleep_time).await;
         }
+        self.emit(Event::Finished);
         Ok(())
     }
 
-    async fn push_communities(&self, info: &Info, dst_info: &Info, sleep_time: Duration) {
+    /// Number of follows and blocks in sections that are not excluded.
+    fn count(info: &Info, parameters: &Parameters) -> usize {
+        [
+            ("follows", info.communities_follows.len()),
+            ("community_blocks", info.communities_blocks.len()),
+            ("person_blocks", info.people_blocks.len()),
+        ]
+            .iter()
+            .filter(|(section, _)| !parameters.excludes(section))
+            .map(|(_, len)| len)
+            .sum()
+    }
+
+    async fn federation(&self) -> Federation {
+        match self.api.federated_instances(&self.user).await {
+            Ok(response) => Federation::new(&instance_host(&self.user.instance), &response),
+            Err(err) => {
+                warn!("Failed to get federated instances, defederation will not be detected: {}", err);
+                Federation::default()
+            },
+        }
+    }
+
+    async fn push_communities(&self, info: &Info, dst_info: &Info, parameters: &Parameters, federation: &Federation, sleep_time: Duration) {
         let iterator = info
             .communities_follows
             .iter()
+            .filter(|_| !parameters.excludes("follows"))
             .filter(|c| !dst_info.communities_follows.contains(c));
         for community in iterator {
-            log_res!(self.follow_community(&community).await);
-            thread::sleep(sleep_time);
+            if let Some(reason) = federation.check(&community.actor) {
+                self.skip_community(Action::Follow, community, reason, federation).await;
+                continue;
+            }
+            let result = self.follow_community(community).await;
+            if let Ok((id, state)) = result {
+                self.report.borrow_mut().subscription(community.actor.as_str(), id, state);
+            }
+            self.record(Action::Follow, &community.actor, result.map(|_| ()));
+            tokio::time::sleep(sleep_time).await;
         }
         let iterator = info
             .communities_blocks
             .iter()
+            .filter(|_| !parameters.excludes("community_blocks"))
             .filter(|c| !dst_info.communities_blocks.contains(c));
         for community in iterator {
-            log_res!(self.block_community(&community).await);
-            thread::sleep(sleep_time);
+            if let Some(reason) = federation.check(&community.actor) {
+                self.skip_community(Action::BlockCommunity, community, reason, federation).await;
+                continue;
+            }
+            self.record(Action::BlockCommunity, &community.actor, self.block_community(community).await);
+            tokio::time::sleep(sleep_time).await;
         }
     }
 
-    async fn push_users(&self, info: &Info, dst_info: &Info, sleep_time: Duration) {
+    async fn push_users(&self, info: &Info, dst_info: &Info, parameters: &Parameters, federation: &Federation, sleep_time: Duration) {
         let iterator = info
             .people_blocks
             .iter()
+            .filter(|_| !parameters.excludes("person_blocks"))
             .filter(|p| !dst_info.people_blocks.contains(p));
         for person in iterator {
-            log_res!(self.block_person(&person).await);
-            thread::sleep(sleep_time);
+            if let Some(reason) = federation.check(&person.actor) {
+                self.skip(Action::BlockPerson, &person.actor, reason, Vec::new());
+                continue;
+            }
+            self.record(Action::BlockPerson, &person.actor, self.block_person(person).await);
+            tokio::time::sleep(sleep_time).await;
         }
     }
 
-    async fn subtractive_push_info(&self, undo_info: &Info, sleep_time: Duration) {
-        for community in undo_info.communities_follows.iter() {
-            log_res!(self.unfollow_community(&community).await);
-            thread::sleep(sleep_time);
+    async fn subtractive_push_info(&self, undo_info: &Info, parameters: &Parameters, sleep_time: Duration) {
+        for community in undo_info.communities_follows.iter().filter(|_| !parameters.excludes("follows")) {
+            self.record(Action::Unfollow, &community.actor, self.unfollow_community(community).await);
+            tokio::time::sleep(sleep_time).await;
         }
-        for community in undo_info.communities_blocks.iter() {
-            log_res!(self.unblock_community(&community).await);
-            thread::sleep(sleep_time);
+        for community in undo_info.communities_blocks.iter().filter(|_| !parameters.excludes("community_blocks")) {
+            self.record(Action::UnblockCommunity, &community.actor, self.unblock_community(community).await);
+            tokio::time::sleep(sleep_time).await;
         }
-        for user in undo_info.people_blocks.iter() {
-            log_res!(self.unblock_person(&user).await);
-            thread::sleep(sleep_time);
+        for user in undo_info.people_blocks.iter().filter(|_| !parameters.excludes("person_blocks")) {
+            self.record(Action::UnblockPerson, &user.actor, self.unblock_person(user).await);
+            tokio::time::sleep(sleep_time).await;
         }
     }
 
-    async fn follow_community(&self, community: &Community) -> Result<(), Error> {
+    /// Remote communities confirm follows asynchronously, so pending ones are checked again after a while.
+    async fn verify_subscriptions(&self) {
+        let pending: Vec<_> = self.report
+            .borrow()
+            .unconfirmed()
+            .map(|s| (s.community.clone(), s.id))
+            .collect();
+        let delay = self.verify_delay.get();
+        if pending.is_empty() || delay.is_zero() {
+            return;
+        }
+        info!("{} follows are pending, checking them again in {} s...", pending.len(), delay.as_secs());
+        tokio::time::sleep(delay).await;
+        for (community, id) in pending.iter() {
+            match self.api.community_by_id(&self.user, id).await {
+                Ok(response) => self.report.borrow_mut().subscription(community, *id, response.community_view.subscribed),
+                Err(err) => warn!("Failed to check subscription of {}: {}", community, err),
+            }
+        }
+    }
+
+    async fn skip_community(&self, action: Action, community: &Community, reason: SkipReason, federation: &Federation) {
+        let suggestions = match self.suggest.get() {
+            true => self.same_named_communities(community, federation).await,
+            false => Vec::new(),
+        };
+        self.skip(action, &community.actor, reason, suggestions);
+    }
+
+    fn skip(&self, action: Action, target: &DbUrl, reason: SkipReason, suggestions: Vec<String>) {
+        warn!("Skipping {}: {}.", target, reason);
+        self.emit(Event::Skipped { action, target: target.as_str(), reason: &reason });
+        self.report.borrow_mut().skip(action, target.as_str(), reason, suggestions);
+    }
+
+    /// Communities with the same name hosted on instances reachable from this account.
+    async fn same_named_communities(&self, community: &Community, federation: &Federation) -> Vec<String> {
+        match self.api.search_community(&self.user, community, 1).await {
+            Ok(response) => response.communities
+                .iter()
+                .filter(|view| view.community.name == community.name)
+                .filter(|view| federation.check(&view.community.actor_id).is_none())
+                .map(|view| view.community.actor_id.to_string())
+                .collect(),
+            Err(err) => {
+                warn!("Failed to search for alternatives of {}: {}", community.name, err);
+                Vec::new()
+            },
+        }
+    }
+
+    fn record(&self, action: Action, target: &DbUrl, result: Result<(), Error>) {
+        let error = match result {
+            Ok(_) => {
+                info!("Success");
+                self.emit(Event::Applied { action, target: target.as_str() });
+                None
+            },
+            Err(err) => {
+                warn!("Failed: {}", err);
+                let error = err.to_string();
+                self.emit(Event::Failed { action, target: target.as_str(), error: &error });
+                Some(error)
+            },
+        };
+        self.report.borrow_mut().add(action, target.as_str(), error);
+    }
+
+    /// Returns state of subscription, which stays pending until community's instance accepts it.
+    async fn follow_community(&self, community: &Community) -> Result<(CommunityId, SubscribedType), Error> {
         info!("Following {}...", community.name);
-        let community_id = self.find_community(&community)
+        let community_id = self.find_community(community)
             .await?;
-        self.api.follow_community(&self.user, &community_id, true)
+        self.emit(Event::Resolved { action: Action::Follow, target: community.actor.as_str() });
+        let response = self.api.follow_community(&self.user, &community_id, true)
             .await?;
-        Ok(())
+        Ok((community_id, response.community_view.subscribed))
     }
 
     async fn unfollow_community(&self, community: &Community) -> Result<(), Error> {
@@ -213,6 +471,7 @@ impl Bliss {
         info!("Blocking {}...", community.name);
         let community_id = self.find_community(community)
             .await?;
+        self.emit(Event::Resolved { action: Action::BlockCommunity, target: community.actor.as_str() });
         self.api.block_community(&self.user, &community_id, true)
             .await?;
         Ok(())
@@ -229,6 +488,7 @@ impl Bliss {
         info!("Blocking {}...", person.username);
         let person_id = self.find_person(person)
             .await?;
+        self.emit(Event::Resolved { action: Action::BlockPerson, target: person.actor.as_str() });
         self.api.block_person(&self.user, &person_id, true)
             .await?;
         Ok(())
@@ -238,45 +498,38 @@ impl Bliss {
         info!("Unblocking {}...", person.username);
         let person_id = self.find_person(person)
             .await?;
+        self.emit(Event::Resolved { action: Action::UnblockPerson, target: person.actor.as_str() });
         self.api.block_person(&self.user, &person_id, false)
             .await?;
         Ok(())
     }
 
+    /// Pages through search results until community with the same actor is found.
     async fn find_community(&self, community: &Community) -> Result<CommunityId, Error> {
-        let response = self.api.search_community(&self.user, &community).await?;
-        let community_id: Option<CommunityId> = {
-            let found: Vec<_> = response.communities
-                .iter()
-                .filter_map(|comm| if community.is_same(comm) { Some(comm.community.id) } else { None })
-                .collect();
-            if found.len() == 1 {
-                Some(found[0])
-            } else {
-                None
+        for page in 1..=SEARCH_MAX_PAGES {
+            let response = self.api.search_community(&self.user, community, page).await?;
+            if let Some(found) = response.communities.iter().find(|view| community.is_same(view)) {
+                return Ok(found.community.id);
             }
-        };
-        let community_id = community_id
-            .ok_or(Error::BlissError(format!("Unable to find community: {}", community.actor)));
-        community_id
+            if (response.communities.len() as i64) < SEARCH_PAGE_LIMIT {
+                break;
+            }
+        }
+        Err(Error::NotFoundError(format!("community: {}", community.actor)))
     }
 
+    /// Pages through search results until person with the same actor is found.
     async fn find_person(&self, person: &Person) -> Result<PersonId, Error> {
-        let response = self.api.search_person(&self.user, &person).await?;
-        let person_id: Option<PersonId> = {
-            let found: Vec<_> = response.users
-                .iter()
-                .filter_map(|pers| if person.is_same(pers) { Some(pers.person.id) } else { None })
-                .collect();
-            if found.len() == 1 {
-                Some(found[0])
-            } else {
-                None
+        for page in 1..=SEARCH_MAX_PAGES {
+            let response = self.api.search_person(&self.user, person, page).await?;
+            if let Some(found) = response.users.iter().find(|view| person.is_same(view)) {
+                return Ok(found.person.id);
             }
-        };
-        let person_id = person_id
-            .ok_or(Error::BlissError(format!("Unable to find user: {}", person.actor)));
-        person_id
+            if (response.users.len() as i64) < SEARCH_PAGE_LIMIT {
+                break;
+            }
+        }
+        Err(Error::NotFoundError(format!("user: {}", person.actor)))
     }
 }
 
@@ -0,0 +1,17 @@
+use std::time::Duration;
+use super::report::{Action, SkipReason};
+
+/// Step of pull or push reported to progress callback.
+pub enum Event<'a> {
+    /// `total` actions are planned, spaced by `interval` to respect rate limit of instance.
+    Started { total: usize, interval: Duration },
+    /// Target was found on instance and action is about to be taken.
+    Resolved { action: Action, target: &'a str },
+    Applied { action: Action, target: &'a str },
+    Failed { action: Action, target: &'a str, error: &'a str },
+    Skipped { action: Action, target: &'a str, reason: &'a SkipReason },
+    Finished,
+}
+
+/// Called for every progress event of pull or push.
+pub type Callback = Box<dyn Fn(&Event)>;
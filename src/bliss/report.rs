@@ -0,0 +1,167 @@
+use std::{fmt, path::PathBuf};
+use serde::Serialize;
+use lemmy_api_common::lemmy_db_schema::{SubscribedType, newtypes::CommunityId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Follow,
+    Unfollow,
+    BlockCommunity,
+    UnblockCommunity,
+    BlockPerson,
+    UnblockPerson,
+    /// Saving part of account to local profile.
+    Pull,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::Follow => "follow",
+            Action::Unfollow => "unfollow",
+            Action::BlockCommunity => "block community",
+            Action::UnblockCommunity => "unblock community",
+            Action::BlockPerson => "block user",
+            Action::UnblockPerson => "unblock user",
+            Action::Pull => "pull",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Why action was not even attempted.
+#[derive(Clone, Serialize)]
+#[serde(tag = "reason", content = "instance", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Destination blocks instance hosting the target.
+    Defederated(String),
+    /// Destination federates only with allowlisted instances, not including one hosting the target.
+    NotAllowed(String),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Defederated(domain) => write!(f, "destination defederated from {}", domain),
+            SkipReason::NotAllowed(domain) => write!(f, "{} is not in destination allowlist", domain),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Entry {
+    pub action: Action,
+    pub target: String,
+    pub error: Option<String>,
+    pub skipped: Option<SkipReason>,
+    /// Same named targets reachable from destination, proposed for skipped ones.
+    pub suggestions: Vec<String>,
+}
+
+/// State of community follow as reported by destination.
+#[derive(Serialize)]
+pub struct Subscription {
+    pub community: String,
+    pub id: CommunityId,
+    pub state: SubscribedType,
+}
+
+/// Outcome of every action taken while pushing to single account.
+#[derive(Serialize)]
+pub struct Report {
+    pub account: String,
+    pub entries: Vec<Entry>,
+    pub subscriptions: Vec<Subscription>,
+}
+
+/// Outcome of pull, with paths of written files.
+#[derive(Serialize)]
+pub struct PullSummary {
+    pub account: String,
+    pub profile_name: String,
+    pub profile: PathBuf,
+    pub avatar: Option<PathBuf>,
+    pub banner: Option<PathBuf>,
+    pub follows: usize,
+    pub community_blocks: usize,
+    pub person_blocks: usize,
+}
+
+impl Report {
+    pub fn new(account: String) -> Self {
+        Report {
+            account,
+            entries: Vec::new(),
+            subscriptions: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, action: Action, target: &str, error: Option<String>) {
+        self.entries.push(Entry {
+            action,
+            target: target.to_owned(),
+            error,
+            skipped: None,
+            suggestions: Vec::new(),
+        });
+    }
+
+    pub fn skip(&mut self, action: Action, target: &str, reason: SkipReason, suggestions: Vec<String>) {
+        self.entries.push(Entry {
+            action,
+            target: target.to_owned(),
+            error: None,
+            skipped: Some(reason),
+            suggestions,
+        });
+    }
+
+    /// Records or updates subscription state of followed community.
+    pub fn subscription(&mut self, community: &str, id: CommunityId, state: SubscribedType) {
+        match self.subscriptions.iter_mut().find(|s| s.community == community) {
+            Some(subscription) => subscription.state = state,
+            None => self.subscriptions.push(Subscription {
+                community: community.to_owned(),
+                id,
+                state,
+            }),
+        }
+    }
+
+    /// Follows not confirmed by community's instance.
+    pub fn unconfirmed(&self) -> impl Iterator<Item = &Subscription> {
+        self.subscriptions.iter().filter(|s| s.state != SubscribedType::Subscribed)
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|e| e.error.is_some())
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|e| e.skipped.is_some())
+    }
+
+    /// True if every action was taken successfully.
+    pub fn is_complete(&self) -> bool {
+        self.failed().count() == 0 && self.skipped().count() == 0
+    }
+
+    pub fn log(&self) {
+        let failed = self.failed().count();
+        let skipped = self.skipped().count();
+        info!("{}: {} actions succeeded, {} failed, {} skipped.", self.account, self.entries.len() - failed - skipped, failed, skipped);
+        for entry in self.failed() {
+            warn!("{}: failed to {} {}: {}", self.account, entry.action, entry.target, entry.error.as_ref().unwrap());
+        }
+        for entry in self.skipped() {
+            warn!("{}: skipped {} {}: {}", self.account, entry.action, entry.target, entry.skipped.as_ref().unwrap());
+            for suggestion in entry.suggestions.iter() {
+                info!("{}: same name on reachable instance: {}", self.account, suggestion);
+            }
+        }
+        for subscription in self.unconfirmed() {
+            warn!("{}: follow of {} is not confirmed, subscription is {}.", self.account, subscription.community, subscription.state);
+        }
+    }
+}
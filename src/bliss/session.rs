@@ -0,0 +1,78 @@
+use std::{fs::{self, DirBuilder, OpenOptions}, io::{Error, ErrorKind, Write}, path::PathBuf};
+use chrono::{DateTime, Local};
+use home::home_dir;
+use serde::{Deserialize, Serialize};
+use crate::user::{User, Authorized};
+use super::util::instance_host;
+
+const SESSION_PATH_RELATIVE: &str = ".bliss/sessions/";
+
+/// Login token cached between runs, so that 2FA code is needed only once per account.
+#[derive(Serialize, Deserialize)]
+struct Session {
+    jwt: String,
+    date_created: DateTime<Local>,
+}
+
+fn path<T>(user: &User<T>) -> Result<PathBuf, Error> {
+    let name = format!("{}@{}", user.username, instance_host(&user.instance))
+        .replace(['/', '\\'], "_");
+    let path = home_dir()
+        .ok_or(Error::new(ErrorKind::NotFound, "Impossible to get home directory."))?
+        .join(SESSION_PATH_RELATIVE)
+        .join(name);
+    Ok(path)
+}
+
+/// Returns cached token of account if there is any. Token is not validated.
+pub fn load<T>(user: &User<T>) -> Result<Option<String>, Error> {
+    let path = path(user)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let session = fs::read_to_string(path)?;
+    let session: Session = serde_yaml::from_str(&session)
+        .map_err(|err| Error::new(ErrorKind::InvalidData, format!("Malformed session file: {}", err)))?;
+    Ok(Some(session.jwt))
+}
+
+/// Caches token in file readable only by its owner, in directory accessible only by its owner.
+pub fn store(user: &User<Authorized>) -> Result<(), Error> {
+    let path = path(user)?;
+    let dir = path.parent().unwrap();
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)?;
+    let session = Session {
+        jwt: user.token().to_owned(),
+        date_created: Local::now(),
+    };
+    let session = serde_yaml::to_string(&session).unwrap();
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        if path.exists() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(session.as_bytes())
+}
+
+/// Removes cached token, returns whether there was any.
+pub fn remove<T>(user: &User<T>) -> Result<bool, Error> {
+    let path = path(user)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(path)?;
+    Ok(true)
+}
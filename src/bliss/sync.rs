@@ -0,0 +1,151 @@
+use std::time::Duration;
+use futures::future;
+use rand::Rng;
+use serde::Serialize;
+use crate::{lemmy::api::Api, user::User, profile::{Profile, local_profile::LocalProfile, three_way::ConflictPolicy, parameters::Parameters}};
+use super::{Bliss, error::Error, report::Report, util::{instance_host, lock, Origin, PasswordSource}};
+
+/// Account and local profile it is pulled to or pushed from, with password source used when there is no cached session.
+#[derive(Clone)]
+pub struct Remote {
+    pub user: User,
+    pub password: Option<PasswordSource>,
+    pub profile_name: String,
+    pub api: Api,
+}
+
+impl Remote {
+    pub fn account(&self) -> String {
+        format!("{}@{}", self.user.username, instance_host(&self.user.instance))
+    }
+
+    pub async fn connect(&self, origin: Origin, token: Option<String>) -> Result<Bliss, Error> {
+        Bliss::connect(self.api.clone(), self.user.clone(), self.password.as_ref(), origin, token, &self.profile_name).await
+    }
+}
+
+/// Push result of single account.
+#[derive(Serialize)]
+pub struct AccountResult {
+    pub account: String,
+    pub report: Option<Report>,
+    pub error: Option<String>,
+}
+
+/// Email is specific to each account, so it is never synced.
+fn strip_account_specific(profile: Profile) -> Profile {
+    let parameters = Parameters::new(&["email".to_owned()], &[]).unwrap();
+    profile.ignore_parameters(&parameters)
+}
+
+/// Synchronizes two accounts both ways through local profile.
+/// Changes made on either account since last sync (kept in profile's sync base) are applied to the other one,
+/// merged state is then saved as local profile and new sync base.
+pub async fn sync(left: &Bliss, right: &Bliss, profile_name: &str, policy: ConflictPolicy) -> Result<(Report, Report), Error> {
+    info!("Syncing {} with {} through local profile {}.", left.account(), right.account(), profile_name);
+    let left_profile = strip_account_specific(left.fetch_profile().await?);
+    let right_profile = strip_account_specific(right.fetch_profile().await?);
+    let mut lp = match LocalProfile::exists(profile_name)? {
+        true => LocalProfile::load(profile_name)?,
+        false => LocalProfile::new(profile_name, left_profile.clone()),
+    };
+    let base = lp.load_sync_base()?;
+    if base.is_none() {
+        warn!("No previous sync found, nothing will be removed from either account.");
+    }
+    let merged = Profile::three_way_merge(base.as_ref(), &left_profile, &right_profile, policy);
+
+    let left_report = left.push_profile(merged.clone(), true, &Parameters::default()).await?;
+    let right_report = right.push_profile(merged.clone(), true, &Parameters::default()).await?;
+
+    lp.profile = merged;
+    lp.save()?;
+    // Failed or skipped actions would look like removals on next sync, so base is kept until both sides succeed.
+    if left_report.is_complete() && right_report.is_complete() {
+        lp.save_sync_base(&lp.profile)?;
+        info!("Synced successfully.");
+    } else {
+        warn!("Some actions failed or were skipped, sync base not updated. Run sync again to retry.");
+    }
+    Ok((left_report, right_report))
+}
+
+/// Pushes the same local profile to several accounts concurrently, each with its own parameters.
+/// Logins run one by one first, so that password prompts do not interleave.
+/// `setup` is called for every connected session, e.g. to set progress callback.
+pub async fn push_many(destinations: &[(Remote, Parameters)], subtractive: bool, setup: impl Fn(&mut Bliss)) -> Vec<AccountResult> {
+    let mut sessions = Vec::new();
+    let mut results = Vec::new();
+    for (remote, parameters) in destinations.iter() {
+        match remote.connect(Origin::Destination, None).await {
+            Ok(mut bliss) => {
+                setup(&mut bliss);
+                sessions.push((parameters, bliss));
+            },
+            Err(err) => {
+                error!("{}: {}", remote.account(), err);
+                results.push(AccountResult { account: remote.account(), report: None, error: Some(err.to_string()) });
+            },
+        }
+    }
+    let pushes = sessions.iter().map(|(parameters, bliss)| async move {
+        (bliss.account(), bliss.push(subtractive, parameters).await)
+    });
+    for (account, result) in future::join_all(pushes).await {
+        match result {
+            Ok(report) => {
+                report.log();
+                results.push(AccountResult { account, report: Some(report), error: None });
+            },
+            Err(err) => {
+                error!("{}: {}", account, err);
+                results.push(AccountResult { account, report: None, error: Some(err.to_string()) });
+            },
+        }
+    }
+    results
+}
+
+/// Fails if push to any of accounts failed.
+pub fn check_pushed(results: &[AccountResult]) -> Result<(), Error> {
+    match results.iter().filter(|r| r.error.is_some()).count() {
+        0 => Ok(()),
+        failed => Err(Error::BlissError(format!("Push failed for {} of {} accounts.", failed, results.len()))),
+    }
+}
+
+/// Periodically pulls source account and pushes it to destinations whenever it changed since last successful push.
+/// Runs until Ctrl-C, waiting `interval` plus random part of `jitter` between runs.
+pub async fn watch(source: &Remote, destinations: &[(Remote, Parameters)], subtractive: bool, interval: Duration, jitter: Duration, setup: impl Fn(&mut Bliss)) -> Result<(), Error> {
+    let _lock = lock(&format!("watch-{}", source.profile_name))?;
+    let mut last_pushed: Option<Profile> = None;
+    loop {
+        match watch_once(source, destinations, subtractive, last_pushed.as_ref(), &setup).await {
+            Ok(Some(profile)) => last_pushed = Some(profile),
+            Ok(None) => {},
+            Err(err) => error!("{}", err),
+        }
+        let delay = interval + rand::thread_rng().gen_range(Duration::ZERO..=jitter);
+        info!("Next run in {} s.", delay.as_secs());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = tokio::signal::ctrl_c() => {
+                info!("Stopped watching.");
+                return Ok(());
+            },
+        }
+    }
+}
+
+/// Returns pushed profile, `None` if there was nothing to push.
+pub async fn watch_once(source: &Remote, destinations: &[(Remote, Parameters)], subtractive: bool, last_pushed: Option<&Profile>, setup: impl Fn(&mut Bliss)) -> Result<Option<Profile>, Error> {
+    let bliss = source.connect(Origin::Source, None).await?;
+    let current = bliss.fetch_profile().await?;
+    if last_pushed.is_some_and(|last| last.same_content(&current)) {
+        info!("No changes in {}, skipping push.", bliss.account());
+        return Ok(None);
+    }
+    bliss.pull().await?;
+    check_pushed(&push_many(destinations, subtractive, setup).await)?;
+    Ok(Some(current))
+}
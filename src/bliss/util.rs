@@ -1,42 +1,181 @@
+use serde::Deserialize;
 use url::Url;
-use std::io::Write;
+use std::{fs, io::Write, path::{Path, PathBuf}, process::{Command, Stdio}, str::FromStr};
+use fs2::FileExt;
+use super::error::Error;
 
 pub enum Origin {
     Source,
     Destination,
 }
 
-pub fn get_password(origin: Origin) -> String {
-    let key = match origin {
-        Origin::Source => "LEMMY_SRC_PW",
-        Origin::Destination => "LEMMY_DST_PW",
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "bliss";
+
+/// Where to take account password from, configured per account or with `--password-source`.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordSource {
+    /// Environment variable with given name.
+    Env(String),
+    /// Always ask user.
+    Prompt,
+    /// Shell command printing password on first line of its output, e.g. `pass show lemmy/main`.
+    Command(String),
+    /// File accessible only by its owner, containing password on first line.
+    File(PathBuf),
+    /// Entry of OS keyring (Secret Service on Linux) stored under service `bliss`.
+    Keyring(String),
+}
+
+impl FromStr for PasswordSource {
+    type Err = String;
+
+    /// Parses `prompt`, `env:NAME`, `command:CMD`, `file:PATH` or `keyring:ENTRY`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            _ if s == "prompt" => Ok(PasswordSource::Prompt),
+            Some(("env", key)) => Ok(PasswordSource::Env(key.to_owned())),
+            Some(("command", cmd)) => Ok(PasswordSource::Command(cmd.to_owned())),
+            Some(("file", path)) => Ok(PasswordSource::File(PathBuf::from(path))),
+            Some(("keyring", entry)) => Ok(PasswordSource::Keyring(entry.to_owned())),
+            _ => Err(format!("Unknown password source \"{}\", expected prompt, env:NAME, command:CMD, file:PATH or keyring:ENTRY.", s)),
+        }
+    }
+}
+
+/// Without explicit source password is searched in `LEMMY_SRC_PW` or `LEMMY_DST_PW`.
+/// User is prompted if environment variable is not set.
+pub fn get_password(origin: Origin, source: Option<&PasswordSource>) -> Result<String, Error> {
+    let key = match (source, origin) {
+        (Some(PasswordSource::Env(key)), _) => key.as_str(),
+        (Some(PasswordSource::Prompt), _) => return Ok(prompt_password("Password: ")),
+        (Some(PasswordSource::Command(cmd)), _) => return password_from_command(cmd),
+        (Some(PasswordSource::File(path)), _) => return password_from_file(path),
+        (Some(PasswordSource::Keyring(entry)), _) => return password_from_keyring(entry),
+        (None, Origin::Source) => "LEMMY_SRC_PW",
+        (None, Origin::Destination) => "LEMMY_DST_PW",
     };
 
     match std::env::var(key) {
-        Ok(pw) => pw,
-        Err(_) => {
-            print!("Password({}): ", key);
-            std::io::stdout().flush().unwrap();
-            let pw = rpassword::read_password().unwrap();
-            pw
+        Ok(pw) => Ok(pw),
+        Err(_) => Ok(prompt_password(&format!("Password({}): ", key))),
+    }
+}
+
+fn first_line(content: &str) -> String {
+    content
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn password_from_command(cmd: &str) -> Result<String, Error> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::BlissError(format!("Password command failed with {}.", output.status)));
+    }
+    Ok(first_line(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn password_from_file(path: &Path) -> Result<String, Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(Error::BlissError(format!(
+                "Password file {} is accessible by other users (mode {:o}), restrict it with chmod 600.",
+                path.display(), mode & 0o777)));
         }
     }
+    Ok(first_line(&fs::read_to_string(path)?))
 }
 
-pub fn instance_host(instance: &Url) -> String {
-    instance
-        .host_str()
-        .unwrap_or(instance.as_str())
-        .to_string()
+#[cfg(feature = "keyring")]
+fn password_from_keyring(entry: &str) -> Result<String, Error> {
+    keyring::Entry::new(KEYRING_SERVICE, entry)
+        .and_then(|entry| entry.get_password())
+        .map_err(|err| Error::BlissError(format!("Failed to read keyring entry {}: {}", entry, err)))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn password_from_keyring(entry: &str) -> Result<String, Error> {
+    Err(Error::BlissError(format!("Cannot read keyring entry {}, bliss is built without keyring feature.", entry)))
+}
+
+/// Stores password in OS keyring to be used with `keyring` password source.
+#[cfg(feature = "keyring")]
+pub fn set_keyring_password(entry: &str) -> Result<(), Error> {
+    let pw = prompt_password(&format!("Password({}): ", entry));
+    keyring::Entry::new(KEYRING_SERVICE, entry)
+        .and_then(|e| e.set_password(&pw))
+        .map_err(|err| Error::BlissError(format!("Failed to write keyring entry {}: {}", entry, err)))
 }
 
-#[macro_export]
-macro_rules! log_res {
-    ( $e:expr ) => {
-        match $e {
-            Ok(_) => info!("Success"),
-            Err(err) => warn!("Failed: {}", err),
+/// Prompts on stderr, so that stdout stays clean for command output.
+#[cfg(feature = "cli")]
+fn prompt_password(prompt: &str) -> String {
+    eprint!("{}", prompt);
+    std::io::stderr().flush().unwrap();
+    rpassword::read_password().unwrap()
+}
+
+/// Without `cli` feature input is not hidden.
+#[cfg(not(feature = "cli"))]
+fn prompt_password(prompt: &str) -> String {
+    eprint!("{}", prompt);
+    std::io::stderr().flush().unwrap();
+    let mut pw = String::new();
+    std::io::stdin().read_line(&mut pw).unwrap();
+    pw.trim_end_matches(['\r', '\n']).to_owned()
+}
+
+/// Gets passphrase of encrypted local profile from `BLISS_PROFILE_PW` or prompts user.
+/// With `confirm` user has to enter new passphrase twice.
+pub fn get_passphrase(profile_name: &str, confirm: bool) -> String {
+    let key = "BLISS_PROFILE_PW";
+    if let Ok(pw) = std::env::var(key) {
+        return pw;
+    }
+    loop {
+        let pw = prompt_password(&format!("Passphrase({}): ", profile_name));
+        if !confirm {
+            return pw;
         }
+        if prompt_password(&format!("Repeat passphrase({}): ", profile_name)) == pw {
+            return pw;
+        }
+        eprintln!("Passphrases do not match.");
     }
 }
 
+/// Takes exclusive lock on `~/.bliss/<name>.lock`, held until returned file is dropped.
+pub fn lock(name: &str) -> Result<fs::File, Error> {
+    let path = home::home_dir()
+        .ok_or(Error::BlissError("Impossible to get home directory.".to_owned()))?
+        .join(".bliss")
+        .join(format!("{}.lock", name));
+    fs::create_dir_all(path.parent().unwrap())?;
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    file.try_lock_exclusive()
+        .map_err(|_| Error::BlissError(format!("Another run holds {}.", path.display())))?;
+    Ok(file)
+}
+
+pub fn instance_host(instance: &Url) -> String {
+    instance
+        .host_str()
+        .unwrap_or(instance.as_str())
+        .to_string()
+}
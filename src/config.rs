@@ -0,0 +1,80 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+use home::home_dir;
+use serde::Deserialize;
+use url::Url;
+use crate::{bliss::{error::Error, util::PasswordSource}, lemmy::client::ClientConfig};
+
+const CONFIG_PATH_RELATIVE: &str = "bliss/config.toml";
+
+/// Contents of `~/.config/bliss/config.toml`.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub accounts: HashMap<String, Account>,
+    /// Named lists of accounts, e.g. `mirrors = ["main", "backup"]`.
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
+    /// HTTP client options shared by all accounts.
+    #[serde(default)]
+    pub client: ClientConfig,
+}
+
+/// Named account, e.g.
+/// ```toml
+/// [accounts.main]
+/// username = "alice"
+/// instance = "https://lemmy.ml"
+/// password = { env = "LEMMY_MAIN_PW" }
+/// profile = "alice"
+/// exclude = ["email"]
+/// ```
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub username: String,
+    pub instance: Url,
+    pub password: Option<PasswordSource>,
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub include: Vec<String>,
+}
+
+impl Config {
+    /// Default location honours `XDG_CONFIG_HOME`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => home_dir()?.join(".config"),
+        };
+        Some(dir.join(CONFIG_PATH_RELATIVE))
+    }
+
+    /// Loads config from given path or default location. Missing default config is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let config = fs::read_to_string(&path)?;
+        toml::from_str(&config)
+            .map_err(|err| Error::ConfigError(format!("{}: {}", path.display(), err)))
+    }
+
+    pub fn account(&self, name: &str) -> Result<&Account, Error> {
+        self.accounts
+            .get(name)
+            .ok_or(Error::ConfigError(format!("No account named {} in config.", name)))
+    }
+
+    pub fn group(&self, name: &str) -> Result<&Vec<String>, Error> {
+        self.groups
+            .get(name)
+            .ok_or(Error::ConfigError(format!("No group named {} in config.", name)))
+    }
+}
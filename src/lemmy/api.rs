@@ -1,38 +1,117 @@
-use crate::profile::Profile;
 use crate::profile::community::Community;
 use crate::profile::person::Person;
 use crate::user::{User, Authorized, NotAuthorized};
-use lemmy_api_common::community::{CommunityResponse, FollowCommunity, BlockCommunity, BlockCommunityResponse};
-use lemmy_api_common::lemmy_db_schema::{SearchType, SortType};
+use lemmy_api_common::community::{CommunityResponse, FollowCommunity, BlockCommunity, BlockCommunityResponse, GetCommunity, GetCommunityResponse};
+use lemmy_api_common::post::{GetPosts, GetPostsResponse};
+use lemmy_api_common::lemmy_db_schema::{SearchType, SortType, ListingType};
 use lemmy_api_common::lemmy_db_schema::newtypes::{CommunityId, PersonId, DbUrl};
 use reqwest::multipart::{Part, Form};
-use reqwest::Client;
+use reqwest::{Client, Method, Response, StatusCode, header::RETRY_AFTER};
+use serde::{Serialize, de::DeserializeOwned};
 use url::Url;
 use lemmy_api_common::sensitive::Sensitive;
 use lemmy_api_common::person::{self, BlockPersonResponse, BlockPerson};
 use lemmy_api_common::site;
 
-use super::LemmyError;
+use super::{LemmyError, trace, client::{ClientConfig, RetryPolicy}};
 use super::image::UploadImageResponse;
 
-const API_BASE: &'static str = "/api/v3"; 
-const PICTRS_BASE : &'static str = "/pictrs/image"; 
+const API_BASE: &str = "/api/v3";
+const PICTRS_BASE : &str = "/pictrs/image";
+/// Results per search page, the most Lemmy allows.
+pub const SEARCH_PAGE_LIMIT: i64 = 50;
 
 fn api_path(instance: &Url, path: &str) -> Url {
     let path = format!("{}/{}", API_BASE, path);
     instance.join(&path).unwrap()
 }
 
+/// Client of Lemmy HTTP API.
+#[derive(Clone)]
 pub struct Api {
     client: Client,
+    retry: RetryPolicy,
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Api {
+    /// Client with default timeouts and user agent.
     pub fn new() -> Self {
-        Api{
-            client: Client::new(),
+        Self::with_config(&ClientConfig::default())
+            .expect("Default HTTP client should build")
+    }
+
+    pub fn with_config(config: &ClientConfig) -> Result<Self, LemmyError> {
+        Ok(Api{
+            client: config.build()?,
+            retry: config.retry_policy(),
+        })
+    }
+
+    /// Sends params as query of GET, as JSON body otherwise. Only for calls unsafe to repeat.
+    async fn call<P: Serialize, R: DeserializeOwned>(&self, method: Method, url: Url, params: &P) -> Result<R, LemmyError> {
+        self.send(method, url, params, 1).await
+    }
+
+    /// Same as `call`, retrying transient failures according to retry policy.
+    async fn call_retrying<P: Serialize, R: DeserializeOwned>(&self, method: Method, url: Url, params: &P) -> Result<R, LemmyError> {
+        self.send(method, url, params, self.retry.max_attempts).await
+    }
+
+    async fn send<P: Serialize, R: DeserializeOwned>(&self, method: Method, url: Url, params: &P, max_attempts: u32) -> Result<R, LemmyError> {
+        let mut attempt = 1;
+        loop {
+            trace::request(&method, &url, params);
+            let request = self.client.request(method.clone(), url.clone());
+            let request = match method {
+                Method::GET => request.query(params),
+                _ => request.json(params),
+            };
+            let result = request.send().await.map_err(reqwest::Error::without_url);
+            match Self::transient_failure(&result) {
+                Some(reason) if attempt < max_attempts => {
+                    let delay = result.as_ref().ok()
+                        .and_then(|response| response.headers().get(RETRY_AFTER))
+                        .and_then(RetryPolicy::retry_after)
+                        .unwrap_or_else(|| self.retry.delay(attempt));
+                    warn!("{} {} failed: {}, retrying in {} ms ({}/{}).",
+                        method, url.path(), reason, delay.as_millis(), attempt + 1, max_attempts);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                },
+                _ => return Self::parse(&method, &url, result?).await,
+            }
+        }
+    }
+
+    /// Overloaded or briefly unreachable instance, worth trying again.
+    fn transient_failure(result: &Result<Response, reqwest::Error>) -> Option<String> {
+        match result {
+            Ok(response) => {
+                let status = response.status();
+                matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT)
+                    .then(|| format!("status {}", status))
+            },
+            Err(err) => (err.is_timeout() || err.is_connect() || err.is_request())
+                .then(|| err.to_string()),
+        }
+    }
+
+    async fn parse<R: DeserializeOwned>(method: &Method, url: &Url, response: Response) -> Result<R, LemmyError> {
+        let status = response.status();
+        let body = response.text().await?;
+        trace::response(method, url, status, &body);
+        if !status.is_success() {
+            return Err(LemmyError::StatusError(status));
         }
+        Ok(serde_json::from_str(&body)?)
     }
+
     pub async fn login(&self, user: User<NotAuthorized>, password: String, token: Option<String>) -> Result<User<Authorized>, LemmyError> {
         let url = api_path(&user.instance, "user/login");
         let params = person::Login {
@@ -40,16 +119,9 @@ impl Api {
             password: Sensitive::new(password),
             totp_2fa_token: token,
         };
-        let response = self.client
-            .post(url)
-            .json(&params)
-            .send()
-            .await;
-        let jwt = response?
-            .json::<person::LoginResponse>().await?
-            .jwt.unwrap()
-            .clone();
-        
+        let response: person::LoginResponse = self.call(Method::POST, url, &params).await?;
+        let jwt = response.jwt
+            .ok_or(LemmyError::ResponseError("No token in login response".to_owned()))?;
         Ok(user.authorize(jwt.to_string()))
     }
 
@@ -58,111 +130,129 @@ impl Api {
         let params = site::GetSite {
             auth: Some(Sensitive::from(user.token()))
         };
-        let response = self.client
-            .get(url)
-            .query(&params)
-            .send()
-            .await?;
-        let result = response.json::<site::GetSiteResponse>().await.unwrap();
-        Ok(result)
+        self.call_retrying(Method::GET, url, &params).await
     }
 
-    pub async fn save_user_settings(&self, user: &User<Authorized>, profile: Profile) -> Result<person::LoginResponse, LemmyError> {
+    pub async fn federated_instances(&self, user: &User<Authorized>) -> Result<site::GetFederatedInstancesResponse, LemmyError> {
+        let url = api_path(&user.instance, "federated_instances");
+        let params = site::GetFederatedInstances {
+            auth: Some(Sensitive::from(user.token()))
+        };
+        self.call_retrying(Method::GET, url, &params).await
+    }
+
+    pub async fn save_user_settings(&self, user: &User<Authorized>, mut settings: person::SaveUserSettings) -> Result<person::LoginResponse, LemmyError> {
         let url = api_path(&user.instance, "user/save_user_settings");
-        let mut settings = person::SaveUserSettings::from(profile);
         settings.auth = Sensitive::from(user.token());
-        let response = self.client
-            .put(url)
-            .json(&settings)
-            .send()
-            .await?;
-        let result = response.json::<person::LoginResponse>().await?;
-        Ok(result)
+        self.call_retrying(Method::PUT, url, &settings).await
     }
 
-    pub async fn search_community(&self, user: &User<Authorized>, community: &Community) -> Result<site::SearchResponse, LemmyError> {
+    /// Page of communities known to instance with name containing community's name, first page is 1.
+    pub async fn search_community(&self, user: &User<Authorized>, community: &Community, page: i64) -> Result<site::SearchResponse, LemmyError> {
         let url = api_path(&user.instance, "search");
         let params = site::Search {
             q: community.name.clone(),
             type_: Some(SearchType::Communities),
             sort: Some(SortType::TopAll),
+            listing_type: Some(ListingType::All),
+            page: Some(page),
+            limit: Some(SEARCH_PAGE_LIMIT),
             auth: Some(Sensitive::from(user.token())),
             ..Default::default()
         };
-        let response = self.client
-            .get(url)
-            .query(&params)
-            .send()
-            .await?;
-        let result = response.json::<site::SearchResponse>().await.unwrap();
-        Ok(result)
+        self.call_retrying(Method::GET, url, &params).await
     }
 
-    pub async fn search_person(&self, user: &User<Authorized>, person: &Person) -> Result<site::SearchResponse, LemmyError> {
+    /// Page of people known to instance with name containing person's name, first page is 1.
+    pub async fn search_person(&self, user: &User<Authorized>, person: &Person, page: i64) -> Result<site::SearchResponse, LemmyError> {
         let url = api_path(&user.instance, "search");
         let params = site::Search {
             q: person.username.clone(),
             type_: Some(SearchType::Users),
             sort: Some(SortType::TopAll),
+            listing_type: Some(ListingType::All),
+            page: Some(page),
+            limit: Some(SEARCH_PAGE_LIMIT),
             auth: Some(Sensitive::from(user.token())),
             ..Default::default()
         };
-        let response = self.client
-            .get(url)
-            .query(&params)
-            .send()
-            .await?;
-        let result = response.json::<site::SearchResponse>().await.unwrap();
-        Ok(result)
+        self.call_retrying(Method::GET, url, &params).await
     }
 
+    /// Community details as seen anonymously on given instance.
+    pub async fn community(&self, instance: &Url, name: &str) -> Result<GetCommunityResponse, LemmyError> {
+        let url = api_path(instance, "community");
+        let params = GetCommunity {
+            name: Some(name.to_owned()),
+            ..Default::default()
+        };
+        self.call_retrying(Method::GET, url, &params).await
+    }
+
+    pub async fn community_by_id(&self, user: &User<Authorized>, id: &CommunityId) -> Result<GetCommunityResponse, LemmyError> {
+        let url = api_path(&user.instance, "community");
+        let params = GetCommunity {
+            id: Some(*id),
+            auth: Some(Sensitive::from(user.token())),
+            ..Default::default()
+        };
+        self.call_retrying(Method::GET, url, &params).await
+    }
+
+    /// Newest posts of community, fetched anonymously.
+    pub async fn latest_posts(&self, instance: &Url, community_name: &str, limit: i64) -> Result<GetPostsResponse, LemmyError> {
+        let url = api_path(instance, "post/list");
+        let params = GetPosts {
+            sort: Some(SortType::New),
+            limit: Some(limit),
+            community_name: Some(community_name.to_owned()),
+            ..Default::default()
+        };
+        self.call_retrying(Method::GET, url, &params).await
+    }
+
+    /// Anonymous search for communities known to instance.
+    pub async fn search_communities(&self, instance: &Url, name: &str, page: i64) -> Result<site::SearchResponse, LemmyError> {
+        let url = api_path(instance, "search");
+        let params = site::Search {
+            q: name.to_owned(),
+            type_: Some(SearchType::Communities),
+            sort: Some(SortType::TopAll),
+            page: Some(page),
+            limit: Some(SEARCH_PAGE_LIMIT),
+            ..Default::default()
+        };
+        self.call_retrying(Method::GET, url, &params).await
+    }
 
     pub async fn follow_community(&self, user: &User<Authorized>, id: &CommunityId, follow: bool) -> Result<CommunityResponse, LemmyError> {
         let url = api_path(&user.instance, "community/follow");
         let params = FollowCommunity {
-            community_id: id.clone(),
+            community_id: *id,
             follow,
             auth: Sensitive::from(user.token()),
         };
-        let response = self.client
-            .post(url)
-            .json(&params)
-            .send()
-            .await?;
-        let result = response.json::<CommunityResponse>().await?;
-        Ok(result)
+        self.call_retrying(Method::POST, url, &params).await
     }
 
     pub async fn block_community(&self, user: &User<Authorized>, id: &CommunityId, block: bool) -> Result<BlockCommunityResponse, LemmyError> {
         let url = api_path(&user.instance, "community/block");
         let params = BlockCommunity {
-            community_id: id.clone(),
+            community_id: *id,
             block,
             auth: Sensitive::from(user.token()),
         };
-        let response = self.client
-            .post(url)
-            .json(&params)
-            .send()
-            .await?;
-        let result = response.json::<BlockCommunityResponse>().await?;
-        Ok(result)
+        self.call_retrying(Method::POST, url, &params).await
     }
 
     pub async fn block_person(&self, user: &User<Authorized>, id: &PersonId, block: bool) -> Result<BlockPersonResponse, LemmyError> {
         let url = api_path(&user.instance, "user/block");
         let params = BlockPerson {
-            person_id: id.clone(),
+            person_id: *id,
             block,
             auth: Sensitive::from(user.token()),
         };
-        let response = self.client
-            .post(url)
-            .json(&params)
-            .send()
-            .await?;
-        let result = response.json::<BlockPersonResponse>().await?;
-        Ok(result)
+        self.call_retrying(Method::POST, url, &params).await
     }
 
     pub async fn download_image(&self, url: &Option<DbUrl>) -> Result<Option<bytes::Bytes>, LemmyError> {
@@ -174,12 +264,16 @@ impl Api {
             .unwrap()
             .inner()
             .to_owned();
-        let bytes = self.client
-            .get(url)
+        trace::request(&Method::GET, &url, &());
+        let response = self.client
+            .get(url.clone())
             .send()
-            .await?
+            .await?;
+        let status = response.status();
+        let bytes = response
             .bytes()
             .await?;
+        trace::response(&Method::GET, &url, status, &format!("<{} bytes>", bytes.len()));
         Ok(Some(bytes))
     }
 
@@ -187,6 +281,7 @@ impl Api {
         let path = user.instance
             .join(PICTRS_BASE)
             .unwrap();
+        trace::request(&Method::POST, &path, &format!("<{} bytes>", bytes.len()));
         let part = Part::bytes(bytes)
             .file_name("image")
             .mime_str("image/png")
@@ -194,15 +289,12 @@ impl Api {
         let form = Form::new()
             .part("images[]", part);
         let response = self.client
-            .post(path)
+            .post(path.clone())
             .header("cookie", format!("jwt={}", user.token()))
             .multipart(form)
             .send()
             .await?;
-        if !response.status().is_success() {
-            return Err(LemmyError::ResponseError(format!("Status is {}", response.status())));
-        }
-        let res: UploadImageResponse = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+        let res: UploadImageResponse = Self::parse(&Method::POST, &path, response).await?;
         if res.msg != "ok" {
             return Err(LemmyError::ResponseError(format!("Msg is {}", res.msg)));
         }
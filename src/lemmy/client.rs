@@ -0,0 +1,111 @@
+use std::{fs, path::{Path, PathBuf}, time::Duration};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{Certificate, Client, Proxy, header::HeaderValue};
+use serde::Deserialize;
+use url::Url;
+use super::LemmyError;
+
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_TIMEOUT: u64 = 60;
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_RETRY_DELAY: u64 = 500;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Options of HTTP client talking to instances, `[client]` section of config, e.g.
+/// ```toml
+/// [client]
+/// timeout = 120
+/// proxy = "socks5h://127.0.0.1:9050"
+/// ca_certificates = ["/etc/ssl/private-ca.pem"]
+/// ```
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Seconds to wait for connection, 10 by default.
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for whole request including response body, 60 by default.
+    pub timeout: Option<u64>,
+    /// HTTP, HTTPS or SOCKS5 proxy for all requests.
+    pub proxy: Option<Url>,
+    /// Replaces default `bliss/<version>` user agent.
+    pub user_agent: Option<String>,
+    /// PEM or DER encoded root certificates trusted in addition to system ones.
+    #[serde(default)]
+    pub ca_certificates: Vec<PathBuf>,
+    /// Accepts invalid and self-signed certificates of any instance.
+    #[serde(default)]
+    pub insecure: bool,
+    /// Attempts of requests safe to repeat before giving up, 3 by default, 1 disables retries.
+    pub max_attempts: Option<u32>,
+    /// Milliseconds before first retry, doubled with each next one, 500 by default.
+    pub retry_delay: Option<u64>,
+}
+
+/// Exponential backoff with jitter for transient failures.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Random delay between half and full of base delay doubled per failed attempt, capped at 30 s.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(MAX_RETRY_DELAY);
+        let millis = delay.as_millis() as u64;
+        Duration::from_millis(rand::thread_rng().gen_range(millis / 2..=millis))
+    }
+
+    /// Delay asked by server in `Retry-After` header, given in seconds or as HTTP date, capped at 30 s.
+    pub fn retry_after(value: &HeaderValue) -> Option<Duration> {
+        let value = value.to_str().ok()?.trim();
+        let delay = match value.parse::<u64>() {
+            Ok(seconds) => Duration::from_secs(seconds),
+            Err(_) => (DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or(Duration::ZERO),
+        };
+        Some(delay.min(MAX_RETRY_DELAY))
+    }
+}
+
+pub fn default_user_agent() -> String {
+    format!("bliss/{} (+https://github.com/skomposzczet/bliss)", env!("CARGO_PKG_VERSION"))
+}
+
+impl ClientConfig {
+    pub fn build(&self) -> Result<Client, LemmyError> {
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT)))
+            .timeout(Duration::from_secs(self.timeout.unwrap_or(DEFAULT_TIMEOUT)))
+            .user_agent(self.user_agent.clone().unwrap_or_else(default_user_agent));
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        for path in self.ca_certificates.iter() {
+            builder = builder.add_root_certificate(Self::certificate(path)?);
+        }
+        if self.insecure {
+            warn!("TLS certificates of instances are not verified.");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        Ok(builder.build()?)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+            base_delay: Duration::from_millis(self.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY)),
+        }
+    }
+
+    fn certificate(path: &Path) -> Result<Certificate, LemmyError> {
+        let bytes = fs::read(path)?;
+        Certificate::from_pem(&bytes)
+            .or_else(|_| Certificate::from_der(&bytes))
+            .map_err(|err| LemmyError::CertificateError(path.display().to_string(), err.without_url()))
+    }
+}
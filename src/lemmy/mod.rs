@@ -1,12 +1,28 @@
 pub mod api;
+pub mod client;
 mod image;
+mod trace;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(thiserror::Error, Debug)]
 pub enum LemmyError {
     #[error(transparent)]
-    ReqwestError( #[from] reqwest::Error ),
+    ReqwestError(reqwest::Error),
     #[error(transparent)]
     IoError( #[from] std::io::Error ),
+    #[error(transparent)]
+    JsonError( #[from] serde_json::Error ),
+    #[error("Invalid certificate {0}: {1}")]
+    CertificateError(String, reqwest::Error),
     #[error("ResponeError: {0}")]
     ResponseError(String),
+    #[error("ResponeError: Status is {0}")]
+    StatusError(reqwest::StatusCode),
+}
+
+/// Request URLs carry login token in query, so they are never kept in errors.
+impl From<reqwest::Error> for LemmyError {
+    fn from(err: reqwest::Error) -> Self {
+        LemmyError::ReqwestError(err.without_url())
+    }
 }
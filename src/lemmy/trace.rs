@@ -0,0 +1,53 @@
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+/// Fields never written to logs.
+const SECRETS: [&str; 7] = ["auth", "jwt", "password", "password_verify", "email", "username_or_email", "totp_2fa_token"];
+
+/// Traces are logged as coming from api module, so that `RUST_LOG=bliss::lemmy::api=trace` selects them.
+const TARGET: &str = "bliss::lemmy::api";
+
+const REDACTED: &str = "<redacted>";
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRETS.contains(&key.as_str()) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    redact(value);
+                }
+            }
+        },
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => (),
+    }
+}
+
+/// Logs request with secrets redacted, on trace level only.
+pub fn request<P: Serialize + ?Sized>(method: &Method, url: &Url, params: &P) {
+    if !log_enabled!(target: TARGET, log::Level::Trace) {
+        return;
+    }
+    let mut params = serde_json::to_value(params).unwrap_or(Value::Null);
+    redact(&mut params);
+    trace!(target: TARGET, "{} {} {}", method, url, params);
+}
+
+/// Logs response with secrets redacted, body not being JSON is logged as is.
+pub fn response(method: &Method, url: &Url, status: StatusCode, body: &str) {
+    if !log_enabled!(target: TARGET, log::Level::Trace) {
+        return;
+    }
+    let body = match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        },
+        Err(_) => body.to_owned(),
+    };
+    trace!(target: TARGET, "{} {} -> {} {}", method, url, status, body);
+}
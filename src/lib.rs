@@ -0,0 +1,36 @@
+//! Bliss is Lemmy Instance Settings Synchronizer.
+//!
+//! Library behind the `bliss` command line tool, for embedding profile sync into other programs.
+//! Account settings, follows and blocks are pulled into local [`Profile`]s (stored in `~/.bliss/profiles`)
+//! and pushed from them to any account by [`Bliss`].
+//!
+//! ```no_run
+//! use bliss::{Bliss, User, Error, profile::parameters::Parameters};
+//!
+//! async fn mirror(password: String) -> Result<(), Error> {
+//!     let user = User::new("alice", &"https://lemmy.ml".parse().unwrap());
+//!     let mut bliss = Bliss::new(user, password, None, "alice").await?;
+//!     bliss.on_progress(|event| { /* update progress bar */ });
+//!     bliss.pull().await?;
+//!     let remote = bliss.fetch_profile().await?;
+//!     let local = bliss::profile::local_profile::LocalProfile::load("alice")?;
+//!     println!("{}", remote.diff(&local.profile));
+//!     let report = bliss.push(false, &Parameters::default()).await?;
+//!     println!("{} actions failed", report.failed().count());
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Default `cli` feature enables dependencies of the binary only, disable it when using the library.
+
+#[macro_use] extern crate log;
+
+pub mod user;
+pub mod lemmy;
+pub mod profile;
+pub mod bliss;
+pub mod config;
+
+pub use crate::bliss::{Bliss, error::Error, report::Report, progress::Event};
+pub use profile::Profile;
+pub use user::User;
@@ -1,22 +1,130 @@
-extern crate pretty_logger;
 #[macro_use] extern crate log;
-use log::LogLevelFilter;
 
-mod user;
-mod lemmy;
-mod profile;
-mod bliss;
-
-use bliss::{Bliss, error::Error, util::{get_password, Origin}};
-use clap::{Parser, Subcommand};
+use std::{cell::Cell, fs, fmt::Write, io::{self, IsTerminal}, path::{Path, PathBuf}, str::FromStr, time::Duration};
+use bliss::{Bliss, Error, Event, bliss::{session, sync::{self, Remote}, audit::Audit, util::{get_password, get_passphrase, set_keyring_password, Origin, PasswordSource}}};
+use bliss::config::{Config, Account};
+use bytes::Bytes;
+use serde::Serialize;
+use serde_json::json;
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle, HumanDuration};
+use clap::{ArgAction, Parser, Subcommand};
+use log::LevelFilter;
+use bliss::profile::{Profile, archive, local_profile::LocalProfile, lemmy_backup::UserSettingsBackup, opml::opml, csv_lists::{self, Section}, three_way::ConflictPolicy, parameters::Parameters, filter::{Filter, ActorPattern}};
 use url::Url;
-use user::User;
+use bliss::User;
+use bliss::lemmy::api::Api;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    #[arg(long, global = true, help="Named account from config file")]
+    account: Option<String>,
+
+    #[arg(long, global = true, help="Config file, defaults to ~/.config/bliss/config.toml")]
+    config: Option<PathBuf>,
+
+    #[arg(long, global = true, help="Password source: prompt, env:NAME, command:CMD, file:PATH or keyring:ENTRY")]
+    password_source: Option<PasswordSource>,
+
+    #[arg(long = "output", id = "output_format", global = true, default_value = "text", help="Output format: text or json, logs always go to stderr")]
+    format: Output,
+
+    #[arg(short, long, global = true, action = ArgAction::Count, help="More verbose logs, -vv traces API requests")]
+    verbose: u8,
+
+    #[arg(short, long, global = true, action = ArgAction::Count, help="Less verbose logs, -qq shows only errors")]
+    quiet: u8,
+
+    #[arg(long, global = true, help="Also append logs to file")]
+    log_file: Option<PathBuf>,
+
+    #[arg(long, global = true, help="Seconds to wait for connection to instance, default 10")]
+    connect_timeout: Option<u64>,
+
+    #[arg(long, global = true, help="Seconds to wait for whole request, default 60")]
+    timeout: Option<u64>,
+
+    #[arg(long, global = true, help="HTTP or SOCKS5 proxy, e.g. socks5h://127.0.0.1:9050")]
+    proxy: Option<Url>,
+
+    #[arg(long, global = true, help="User-Agent header, defaults to bliss/<version>")]
+    user_agent: Option<String>,
+
+    #[arg(long, global = true, help="Trust additional root certificate (PEM or DER), may be repeated")]
+    ca_cert: Vec<PathBuf>,
+
+    #[arg(long, global = true, help="Accept invalid and self-signed certificates of instances")]
+    insecure: bool,
+
+    #[arg(long, global = true, help="Attempts of requests safe to repeat when instance fails transiently, default 3")]
+    max_attempts: Option<u32>,
+
+    /// Whether command result was printed, so that failure does not add second JSON document.
+    #[arg(skip)]
+    printed: Cell<bool>,
+}
+
+/// Format of command results printed to stdout.
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Text,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("Unknown output format \"{}\", expected text or json.", s)),
+        }
+    }
+}
+
+impl Cli {
+    /// API client configured by `[client]` section of config, overridden by arguments.
+    fn api(&self) -> Result<Api, Error> {
+        let mut client = Config::load(self.config.as_deref())?.client;
+        client.connect_timeout = self.connect_timeout.or(client.connect_timeout);
+        client.timeout = self.timeout.or(client.timeout);
+        client.proxy = self.proxy.clone().or(client.proxy);
+        client.user_agent = self.user_agent.clone().or(client.user_agent);
+        client.ca_certificates.extend(self.ca_cert.iter().cloned());
+        client.insecure |= self.insecure;
+        client.max_attempts = self.max_attempts.or(client.max_attempts);
+        Ok(Api::with_config(&client)?)
+    }
+
+    /// Level given by `-v`/`-q`, `RUST_LOG` may still override it per module.
+    fn log_level(&self) -> LevelFilter {
+        match self.verbose as i8 - self.quiet as i8 {
+            i8::MIN..=-2 => LevelFilter::Error,
+            -1 => LevelFilter::Warn,
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+
+    /// Prints command result to stdout in JSON output mode, text mode reports through logs.
+    fn print_json<T: Serialize>(&self, result: &T) {
+        if self.format == Output::Json {
+            println!("{}", serde_json::to_string_pretty(result).unwrap());
+            self.printed.set(true);
+        }
+    }
+
+    /// Prints error unless result already printed describes it.
+    fn print_error(&self, err: &Error) {
+        if !self.printed.get() {
+            self.print_json(&json!({ "error": err.to_string() }));
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -24,66 +132,679 @@ enum Commands {
     /// Pull account settings to local profile
     Pull {
         #[arg(short, long, help="Source username or email")]
-        username: String,
+        username: Option<String>,
 
         #[arg(short, long, help="Source instance")]
-        instance: Url,
+        instance: Option<Url>,
 
         #[arg(short, long, help="2FA token for source account")]
         token: Option<String>,
 
         #[arg(short, long, help="Local profile name")]
-        profile_name: String,
+        profile_name: Option<String>,
     },
     /// Push account settings from local profile
     Push {
         #[arg(short, long, help="Destination username or email")]
-        username: String,
+        username: Option<String>,
 
         #[arg(short, long, help="Destination instance")]
-        instance: Url,
+        instance: Option<Url>,
 
         #[arg(short, long, help="2FA token for destination account")]
         token: Option<String>,
 
         #[arg(short, long, help="Local profile name")]
-        profile_name: String,
+        profile_name: Option<String>,
 
         #[arg(short, long, help="Unfollows and unblocks communities and users if not followed or blocked in local profile")]
         subtractive: bool,
 
-        #[arg(long, help="Parameters to exclude while pushing")]
+        #[arg(long, value_delimiter = ',', help="Parameters, groups or wildcards to exclude while pushing, e.g. notifications,show_*")]
         exclude: Vec<String>,
 
-        #[arg(long, help="Nondefault parameters to include while pushing")]
+        #[arg(long, value_delimiter = ',', help="Nondefault parameters to include while pushing: avatar, banner")]
         include: Vec<String>,
+
+        #[arg(long, help="Push only follows and blocks matching pattern: domain:<instance>, name:<glob> or regex:<regex>")]
+        only: Vec<ActorPattern>,
+
+        #[arg(long, help="Skip follows and blocks matching pattern: domain:<instance>, name:<glob> or regex:<regex>")]
+        skip: Vec<ActorPattern>,
+
+        #[arg(long, help="Suggest same named communities on other instances for ones hosted on defederated instances")]
+        suggest: bool,
+
+        #[arg(long, default_value_t = 0, help="Seconds to wait after push before checking pending follows again, 0 skips the check")]
+        verify_after: u64,
+
+        #[arg(long, value_delimiter = ',', help="Named accounts from config to push to concurrently, instead of single account")]
+        to: Vec<String>,
+
+        #[arg(long, help="Group of accounts from config to push to concurrently, instead of single account")]
+        group: Option<String>,
+    },
+    /// Sync two named accounts both ways through local profile
+    Sync {
+        #[arg(short, long, help="Local profile name, keeps state of last sync")]
+        profile_name: String,
+
+        #[arg(long, help="Named account from config")]
+        left: String,
+
+        #[arg(long, help="Named account from config")]
+        right: String,
+
+        #[arg(long, default_value = "prompt", help="Field changed on both sides: prefer-left, prefer-right or prompt")]
+        on_conflict: ConflictPolicy,
+    },
+    /// Periodically pull named account and push it to other named accounts when it changes
+    Watch {
+        #[arg(short, long, help="Local profile name")]
+        profile_name: String,
+
+        #[arg(long, help="Named source account from config")]
+        source: String,
+
+        #[arg(long, value_delimiter = ',', help="Named destination accounts from config")]
+        to: Vec<String>,
+
+        #[arg(long, help="Group of destination accounts from config")]
+        group: Option<String>,
+
+        #[arg(short, long, help="Unfollows and unblocks communities and users if not followed or blocked in source account")]
+        subtractive: bool,
+
+        #[arg(long, default_value_t = 3600, help="Seconds between runs")]
+        interval: u64,
+
+        #[arg(long, default_value_t = 300, help="Maximum random seconds added to interval")]
+        jitter: u64,
+    },
+    /// Merge local profiles into new one, uniting follows and blocks
+    Merge {
+        #[arg(num_args = 2.., required = true, help="Local profile names")]
+        profiles: Vec<String>,
+
+        #[arg(short, long = "out", help="Merged local profile name")]
+        output: String,
+
+        #[arg(long, help="Profile to take settings, bio and images from, defaults to first one")]
+        primary: Option<String>,
+
+        #[arg(short, long, help="Overwrite merged profile if it already exists")]
+        force: bool,
+    },
+    /// Export local profile to single archive file
+    Export {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+
+        #[arg(short, long = "out", help="Archive path")]
+        output: PathBuf,
+    },
+    /// Import local profile from archive file
+    Import {
+        #[arg(help="Archive path")]
+        archive: PathBuf,
+
+        #[arg(short, long, help="Local profile name, defaults to name stored in archive")]
+        profile_name: Option<String>,
+
+        #[arg(short, long, help="Overwrite local profile if it already exists")]
+        force: bool,
+    },
+    /// Encrypt local profile file with passphrase
+    Encrypt {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+    },
+    /// Store local profile file in plaintext again
+    Decrypt {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+    },
+    /// Convert Lemmy settings backup to local profile
+    ImportLemmyBackup {
+        #[arg(help="Lemmy backup JSON file")]
+        file: PathBuf,
+
+        #[arg(short, long, help="Username of account the backup belongs to")]
+        username: String,
+
+        #[arg(short, long, help="Instance of account the backup belongs to")]
+        instance: Url,
+
+        #[arg(short, long, help="Local profile name")]
+        profile_name: String,
+    },
+    /// Convert local profile to Lemmy settings backup
+    ExportLemmyBackup {
+        #[arg(short, long, help="Local profile name")]
+        profile_name: String,
+
+        #[arg(short, long = "out", help="Lemmy backup JSON file")]
+        output: PathBuf,
+    },
+    /// Export RSS feeds of followed communities as OPML
+    ExportOpml {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+
+        #[arg(short, long = "out", help="OPML file")]
+        output: PathBuf,
+
+        #[arg(short, long, help="Include subscribed front page feed, requires login to profile's account")]
+        front_page: bool,
+
+        #[arg(short, long, help="2FA token for profile's account")]
+        token: Option<String>,
+    },
+    /// Show what push of local profile would change on its account, or how it differs from another local profile
+    Diff {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+
+        #[arg(long, help="Local profile to compare with instead of profile's account")]
+        against: Option<String>,
+
+        #[arg(short, long, help="2FA token for profile's account")]
+        token: Option<String>,
+    },
+    /// Find deleted, removed or inactive communities followed in local profile
+    Audit {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+
+        #[arg(long, default_value_t = 180, help="Days without new posts after which community is considered inactive")]
+        max_age: i64,
+
+        #[arg(long, help="Propose active communities with the same name, searched on profile's instance")]
+        suggest: bool,
+    },
+    /// Export followed or blocked communities or blocked users as CSV
+    ExportCsv {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+
+        #[arg(short, long, help="One of: follows, community-blocks, person-blocks")]
+        section: Section,
+
+        #[arg(short, long = "out", help="CSV file")]
+        output: PathBuf,
+    },
+    /// Import followed or blocked communities or blocked users from CSV
+    ImportCsv {
+        #[arg(help="Local profile name")]
+        profile_name: String,
+
+        #[arg(short, long, help="One of: follows, community-blocks, person-blocks")]
+        section: Section,
+
+        #[arg(help="CSV file with actor column and optional name column")]
+        file: PathBuf,
+
+        #[arg(short, long, help="Replace section instead of adding to it")]
+        replace: bool,
+    },
+    /// Log in and cache session, so that later commands need neither password nor 2FA token
+    Login {
+        #[arg(short, long, help="Username or email")]
+        username: Option<String>,
+
+        #[arg(short, long, help="Instance")]
+        instance: Option<Url>,
+
+        #[arg(short, long, help="2FA token")]
+        token: Option<String>,
+    },
+    /// Remove cached session
+    Logout {
+        #[arg(short, long, help="Username or email")]
+        username: Option<String>,
+
+        #[arg(short, long, help="Instance")]
+        instance: Option<Url>,
+    },
+    /// Store account password in OS keyring
+    KeyringSet {
+        #[arg(help="Keyring entry name, used as keyring:ENTRY password source")]
+        entry: String,
     },
 }
 
 #[tokio::main]
 async fn main() {
-    pretty_logger::init_level(LogLevelFilter::Info).unwrap();
     let cli = Cli::parse();
-    if let Err(err) = exec_command(&cli).await {
+    let bars = MultiProgress::new();
+    if let Err(err) = init_logger(cli.log_level(), cli.log_file.as_deref(), &bars) {
+        eprintln!("Failed to open log file: {}", err);
+        std::process::exit(1);
+    }
+    if let Err(err) = exec_command(&cli, &bars).await {
         error!("{}", err);
+        cli.print_error(&err);
+        std::process::exit(1);
+    }
+}
+
+/// Writes logs to stderr above progress bars, and to file if given.
+struct LogWriter {
+    bars: MultiProgress,
+    file: Option<fs::File>,
+}
+
+impl io::Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bars.suspend(|| io::stderr().write_all(buf))?;
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()?;
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `level` applies to bliss, other crates log warnings at most.
+/// Module filters from `RUST_LOG` (e.g. `bliss::lemmy::api=trace`) apply on top of it.
+/// Logs are printed through `bars`, so that they do not garble progress bars.
+fn init_logger(level: LevelFilter, log_file: Option<&Path>, bars: &MultiProgress) -> io::Result<()> {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(level.min(LevelFilter::Warn))
+        .filter_module("bliss", level);
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    let file = match log_file {
+        Some(path) => Some(fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?),
+        None => None,
+    };
+    // Colors only on terminal, never in log file.
+    let style = match file.is_none() && io::stderr().is_terminal() {
+        true => env_logger::WriteStyle::Always,
+        false => env_logger::WriteStyle::Never,
+    };
+    builder
+        .target(env_logger::Target::Pipe(Box::new(LogWriter { bars: bars.clone(), file })))
+        .write_style(style)
+        .init();
+    Ok(())
+}
+
+/// Account and profile to operate on, from command line arguments or named account in config.
+/// Arguments take precedence over config values.
+struct Target {
+    remote: Remote,
+    exclude: Vec<String>,
+    include: Vec<String>,
+}
+
+impl Target {
+    fn resolve(cli: &Cli, username: &Option<String>, instance: &Option<Url>, profile_name: &Option<String>) -> Result<Target, Error> {
+        Self::resolve_with(cli, username, instance, profile_name, true)
+    }
+
+    /// Resolves only account, for commands not touching local profile.
+    fn resolve_account(cli: &Cli, username: &Option<String>, instance: &Option<Url>) -> Result<Target, Error> {
+        Self::resolve_with(cli, username, instance, &None, false)
+    }
+
+    fn resolve_with(cli: &Cli, username: &Option<String>, instance: &Option<Url>, profile_name: &Option<String>, needs_profile: bool) -> Result<Target, Error> {
+        let account = match &cli.account {
+            Some(name) => Some(Config::load(cli.config.as_deref())?.account(name)?.clone()),
+            None => None,
+        };
+        Self::from_args(cli, account.as_ref(), username, instance, profile_name, needs_profile)
+    }
+
+    /// Resolves every named account, all sharing the same local profile.
+    fn resolve_many(cli: &Cli, names: &[String], profile_name: &Option<String>) -> Result<Vec<Target>, Error> {
+        let config = Config::load(cli.config.as_deref())?;
+        names
+            .iter()
+            .map(|name| Self::from_args(cli, Some(config.account(name)?), &None, &None, profile_name, true))
+            .collect()
+    }
+
+    fn from_args(cli: &Cli, account: Option<&Account>, username: &Option<String>, instance: &Option<Url>, profile_name: &Option<String>, needs_profile: bool) -> Result<Target, Error> {
+        let missing = |arg: &str| Error::BlissError(format!("Missing {}, pass it as argument or select account with --account.", arg));
+        let username = username.clone()
+            .or(account.as_ref().map(|a| a.username.clone()))
+            .ok_or(missing("username"))?;
+        let instance = instance.clone()
+            .or(account.as_ref().map(|a| a.instance.clone()))
+            .ok_or(missing("instance"))?;
+        let profile_name = match profile_name.clone().or(account.as_ref().and_then(|a| a.profile.clone())) {
+            Some(name) => name,
+            None if needs_profile => return Err(missing("profile name")),
+            None => String::new(),
+        };
+        Ok(Target {
+            remote: Remote {
+                user: User::new(&username, &instance),
+                password: cli.password_source.clone().or(account.as_ref().and_then(|a| a.password.clone())),
+                profile_name,
+                api: cli.api()?,
+            },
+            exclude: account.as_ref().map(|a| a.exclude.clone()).unwrap_or_default(),
+            include: account.map(|a| a.include.clone()).unwrap_or_default(),
+        })
+    }
+
+    /// Parameters given as arguments override ones from config.
+    fn parameters(&self, exclude: &[String], include: &[String]) -> Result<Parameters, Error> {
+        let exclude = if exclude.is_empty() { &self.exclude } else { exclude };
+        let include = if include.is_empty() { &self.include } else { include };
+        Parameters::new(exclude, include).map_err(Error::ParameterError)
     }
 }
 
-async fn exec_command(cli: &Cli) -> Result<(), Error> {
+/// Renders progress of pull or push as bar, with ETA following rate limit of instance.
+fn progress_bar(bars: &MultiProgress, account: String) -> impl Fn(&Event) {
+    let bar = bars.add(ProgressBar::new(0));
+    bar.set_prefix(account);
+    move |event| match event {
+        Event::Started { total, interval } => {
+            let interval = *interval;
+            let style = ProgressStyle::with_template("{prefix} [{bar:30}] {pos}/{len} ETA {limit_eta} {wide_msg}")
+                .unwrap()
+                .with_key("limit_eta", move |state: &ProgressState, w: &mut dyn Write| {
+                    let left = state.len().unwrap_or(0).saturating_sub(state.pos());
+                    write!(w, "{}", HumanDuration(interval * left as u32)).unwrap();
+                })
+                .progress_chars("=> ");
+            bar.set_style(style);
+            bar.set_length(*total as u64);
+        },
+        Event::Resolved { action, target } => bar.set_message(format!("{} {}", action, target)),
+        Event::Applied { action, target } => {
+            bar.set_message(format!("{} {}", action, target));
+            bar.inc(1);
+        },
+        Event::Failed { .. } | Event::Skipped { .. } => bar.inc(1),
+        Event::Finished => bar.finish_and_clear(),
+    }
+}
+
+/// Pairs every target with its parameters, arguments override ones from config.
+fn destinations(targets: Vec<Target>, exclude: &[String], include: &[String], filter: &Filter) -> Result<Vec<(Remote, Parameters)>, Error> {
+    targets
+        .into_iter()
+        .map(|target| {
+            let parameters = target.parameters(exclude, include)?.with_filter(filter.clone());
+            Ok((target.remote, parameters))
+        })
+        .collect()
+}
+
+async fn exec_command(cli: &Cli, bars: &MultiProgress) -> Result<(), Error> {
     match &cli.command {
         Some(Commands::Pull { username, instance, token, profile_name }) => {
-            let pw = get_password(Origin::Source);
-            let user = User::new(username, instance);
-            let bliss = Bliss::new(user, pw, token.to_owned(), profile_name).await?;
-            bliss.pull().await?;
+            let target = Target::resolve(cli, username, instance, profile_name)?;
+            let mut bliss = target.remote.connect(Origin::Source, token.clone()).await?;
+            bliss.on_progress(progress_bar(bars, bliss.account()));
+            cli.print_json(&bliss.pull().await?);
+        },
+        Some(Commands::Push { username, instance, token, profile_name, subtractive, exclude , include, only, skip, suggest, verify_after, to, group }) => {
+            let filter = Filter::new(only, skip);
+            let mut accounts = to.clone();
+            if let Some(group) = group {
+                accounts.extend(Config::load(cli.config.as_deref())?.group(group)?.iter().cloned());
+            }
+            if !accounts.is_empty() {
+                let destinations = destinations(Target::resolve_many(cli, &accounts, profile_name)?, exclude, include, &filter)?;
+                let results = sync::push_many(&destinations, *subtractive, |bliss| {
+                    bliss.on_progress(progress_bar(bars, bliss.account()));
+                    bliss.suggest_alternatives(*suggest);
+                    bliss.verify_subscriptions_after(Duration::from_secs(*verify_after));
+                }).await;
+                let checked = sync::check_pushed(&results);
+                cli.print_json(&json!({ "results": results, "error": checked.as_ref().err().map(Error::to_string) }));
+                return checked;
+            }
+            let target = Target::resolve(cli, username, instance, profile_name)?;
+            let parameters = target.parameters(exclude, include)?.with_filter(filter);
+            let mut bliss = target.remote.connect(Origin::Destination, token.clone()).await?;
+            bliss.on_progress(progress_bar(bars, bliss.account()));
+            bliss.suggest_alternatives(*suggest);
+            bliss.verify_subscriptions_after(Duration::from_secs(*verify_after));
+            let report = bliss.push(*subtractive, &parameters).await?;
+            report.log();
+            cli.print_json(&report);
+        },
+        Some(Commands::Sync { profile_name, left, right, on_conflict }) => {
+            let profile_name = Some(profile_name.clone());
+            let targets = Target::resolve_many(cli, &[left.clone(), right.clone()], &profile_name)?;
+            let mut left = targets[0].remote.connect(Origin::Source, None).await?;
+            let mut right = targets[1].remote.connect(Origin::Destination, None).await?;
+            left.on_progress(progress_bar(bars, left.account()));
+            right.on_progress(progress_bar(bars, right.account()));
+            let (left_report, right_report) = sync::sync(&left, &right, &targets[0].remote.profile_name, *on_conflict).await?;
+            left_report.log();
+            right_report.log();
+            cli.print_json(&json!({ "left": left_report, "right": right_report }));
+        },
+        Some(Commands::Watch { profile_name, source, to, group, subtractive, interval, jitter }) => {
+            let mut accounts = to.clone();
+            if let Some(group) = group {
+                accounts.extend(Config::load(cli.config.as_deref())?.group(group)?.iter().cloned());
+            }
+            if accounts.is_empty() {
+                return Err(Error::BlissError("No destination accounts, use --to or --group.".to_owned()));
+            }
+            let profile_name = Some(profile_name.clone());
+            let source = Target::resolve_many(cli, std::slice::from_ref(source), &profile_name)?.remove(0).remote;
+            let destinations = destinations(Target::resolve_many(cli, &accounts, &profile_name)?, &[], &[], &Filter::default())?;
+            let (interval, jitter) = (Duration::from_secs(*interval), Duration::from_secs(*jitter));
+            sync::watch(&source, &destinations, *subtractive, interval, jitter, |bliss| {
+                bliss.on_progress(progress_bar(bars, bliss.account()));
+            }).await?;
+        },
+        Some(Commands::Login { username, instance, token }) => {
+            let remote = Target::resolve_account(cli, username, instance)?.remote;
+            session::remove(&remote.user)?;
+            let pw = get_password(Origin::Source, remote.password.as_ref())?;
+            Bliss::new_with(remote.api.clone(), remote.user.clone(), pw, token.to_owned(), "").await?
+                .remember_session()?;
+            info!("Logged in as {}, session cached.", remote.account());
+            cli.print_json(&json!({ "account": remote.account() }));
         },
-        Some(Commands::Push { username, instance, token, profile_name, subtractive, exclude , include }) => {
-            let pw = get_password(Origin::Destination);
-            let user = User::new(username, instance);
-            let bliss = Bliss::new(user, pw, token.to_owned(), profile_name).await?;
-            bliss.push(*subtractive, exclude, include).await?;
+        Some(Commands::Logout { username, instance }) => {
+            let remote = Target::resolve_account(cli, username, instance)?.remote;
+            let removed = session::remove(&remote.user)?;
+            match removed {
+                true => info!("Removed cached session of {}.", remote.account()),
+                false => warn!("No cached session of {}.", remote.account()),
+            }
+            cli.print_json(&json!({ "removed": removed }));
+        },
+        Some(Commands::Merge { profiles, output, primary, force }) => {
+            let primary = primary.as_ref().unwrap_or(&profiles[0]);
+            if !profiles.contains(primary) {
+                return Err(Error::BlissError(format!("Primary profile {} is not one of merged profiles.", primary)));
+            }
+            if LocalProfile::exists(output)? && !*force {
+                return Err(Error::BlissError(format!("Local profile {} already exists.", output)));
+            }
+            let primary = LocalProfile::load(primary)?;
+            let others = profiles
+                .iter()
+                .filter(|name| **name != primary.name)
+                .map(|name| LocalProfile::load(name).map(|lp| lp.profile))
+                .collect::<Result<Vec<_>, _>>()?;
+            let merged = Profile::merge(&primary.profile, &others.iter().collect::<Vec<_>>());
+            let mut lp = LocalProfile::new(output, merged);
+            lp.save()?;
+            lp.save_avatar(primary.load_avatar()?.map(Bytes::from))?;
+            lp.save_banner(primary.load_banner()?.map(Bytes::from))?;
+            info!("Merged {} profiles into {}.", profiles.len(), output);
+            cli.print_json(&json!({ "profile": output, "merged": profiles, "path": lp.profile_path()? }));
+        },
+        Some(Commands::Export { profile_name, output }) => {
+            archive::export(profile_name, output)?;
+            info!("Exported profile {} to {}.", profile_name, output.display());
+            cli.print_json(&json!({ "profile": profile_name, "path": output }));
+        },
+        Some(Commands::Import { archive, profile_name, force }) => {
+            let name = archive::import(archive, profile_name.as_deref(), *force)?;
+            info!("Imported profile {} from {}.", name, archive.display());
+            cli.print_json(&json!({ "profile": name, "path": archive }));
+        },
+        Some(Commands::Encrypt { profile_name }) => {
+            let mut lp = LocalProfile::load(profile_name)?;
+            lp.change_passphrase(Some(get_passphrase(profile_name, true)))?;
+            info!("Encrypted profile {}.", profile_name);
+            cli.print_json(&json!({ "profile": profile_name, "encrypted": true }));
+        },
+        Some(Commands::Decrypt { profile_name }) => {
+            let mut lp = LocalProfile::load(profile_name)?;
+            if lp.is_encrypted() {
+                lp.change_passphrase(None)?;
+                info!("Decrypted profile {}.", profile_name);
+            } else {
+                warn!("Profile {} is not encrypted.", profile_name);
+            }
+            cli.print_json(&json!({ "profile": profile_name, "encrypted": false }));
+        },
+        Some(Commands::ImportLemmyBackup { file, username, instance, profile_name }) => {
+            let backup = fs::read_to_string(file)?;
+            let backup: UserSettingsBackup = serde_json::from_str(&backup)
+                .map_err(|err| Error::BlissError(format!("Malformed Lemmy backup: {}", err)))?;
+            let unsupported = backup.unsupported();
+            for item in unsupported.iter() {
+                warn!("Skipping {}, not supported by local profile.", item);
+            }
+            let profile = Profile::from_backup(User::new(username, instance), &backup);
+            let mut lp = LocalProfile::new(profile_name, profile);
+            lp.save()?;
+            info!("Imported Lemmy backup to profile {}.", profile_name);
+            cli.print_json(&json!({ "profile": profile_name, "path": lp.profile_path()?, "skipped": unsupported }));
+        },
+        Some(Commands::ExportLemmyBackup { profile_name, output }) => {
+            let lp = LocalProfile::load(profile_name)?;
+            let backup = UserSettingsBackup::from(&lp.profile);
+            fs::write(output, serde_json::to_string_pretty(&backup).unwrap())?;
+            info!("Exported profile {} to Lemmy backup {}.", profile_name, output.display());
+            cli.print_json(&json!({ "profile": profile_name, "path": output }));
+        },
+        Some(Commands::ExportOpml { profile_name, output, front_page, token }) => {
+            let lp = LocalProfile::load(profile_name)?;
+            let front_page = match front_page {
+                true => {
+                    let bliss = Bliss::connect(cli.api()?, lp.profile.user(), cli.password_source.as_ref(), Origin::Source, token.clone(), profile_name).await?;
+                    warn!("Front page feed contains login token, do not share {}.", output.display());
+                    Some(bliss.front_page_feed())
+                },
+                false => None,
+            };
+            fs::write(output, opml(&lp.profile, front_page.as_ref()))?;
+            info!("Exported {} community feeds to {}.", lp.profile.info.communities_follows.len(), output.display());
+            cli.print_json(&json!({ "profile": profile_name, "path": output, "feeds": lp.profile.info.communities_follows.len() }));
+        },
+        Some(Commands::Diff { profile_name, against, token }) => {
+            let lp = LocalProfile::load(profile_name)?;
+            let other = match against {
+                Some(other) => LocalProfile::load(other)?.profile,
+                None => Bliss::connect(cli.api()?, lp.profile.user(), cli.password_source.as_ref(), Origin::Destination, token.clone(), profile_name)
+                    .await?
+                    .fetch_profile()
+                    .await?,
+            };
+            let diff = other.diff(&lp.profile);
+            match (cli.format, diff.is_empty()) {
+                (Output::Json, _) => cli.print_json(&diff),
+                (Output::Text, true) => info!("No differences."),
+                (Output::Text, false) => print!("{}", diff),
+            }
+        },
+        Some(Commands::Audit { profile_name, max_age, suggest }) => {
+            let lp = LocalProfile::load(profile_name)?;
+            let search_instance = suggest.then(|| lp.profile.user().instance);
+            let findings = Audit::new(cli.api()?, *max_age, search_instance).run(&lp.profile).await;
+            info!("{} of {} followed communities look dead.", findings.len(), lp.profile.info.communities_follows.len());
+            for finding in findings.iter() {
+                match finding.subscribers {
+                    Some(subscribers) => warn!("{}: {}, {} subscribers.", finding.community.actor, finding.cause, subscribers),
+                    None => warn!("{}: {}.", finding.community.actor, finding.cause),
+                }
+                for alternative in finding.alternatives.iter() {
+                    info!("{}: alternative {} with {} subscribers.", finding.community.actor, alternative.actor, alternative.subscribers);
+                }
+            }
+            cli.print_json(&findings);
+        },
+        Some(Commands::ExportCsv { profile_name, section, output }) => {
+            let lp = LocalProfile::load(profile_name)?;
+            let count = csv_lists::export(&lp.profile.info, *section, fs::File::create(output)?)?;
+            info!("Exported {} entries to {}.", count, output.display());
+            cli.print_json(&json!({ "profile": profile_name, "path": output, "entries": count }));
+        },
+        Some(Commands::ImportCsv { profile_name, section, file, replace }) => {
+            let mut lp = LocalProfile::load(profile_name)?;
+            let count = csv_lists::import(&mut lp.profile.info, *section, fs::File::open(file)?, *replace)?;
+            lp.save()?;
+            info!("Imported {} new entries to profile {}.", count, profile_name);
+            cli.print_json(&json!({ "profile": profile_name, "added": count }));
+        },
+        Some(Commands::KeyringSet { entry }) => {
+            set_keyring_password(entry)?;
+            info!("Stored password in keyring entry {}.", entry);
+            cli.print_json(&json!({ "entry": entry }));
         },
         None => {}
     }
     Ok(()) 
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+    use serde_json::json;
+    use super::{Cli, Output};
+
+    /// Catches clashing argument ids and names, which clap reports only when parsing.
+    #[test]
+    fn cli_is_consistent() {
+        let mut command = Cli::command();
+        command.build();
+        command.clone().debug_assert();
+        for subcommand in command.get_subcommands() {
+            subcommand.clone().debug_assert();
+        }
+    }
+
+    #[test]
+    fn output_file_and_format_parse_together() {
+        for args in [
+            "bliss export p -o x.tgz",
+            "bliss merge a b -o c",
+            "bliss export-lemmy-backup -p p -o x.json",
+            "bliss export-opml p -o x.opml",
+            "bliss export-csv p -s follows --out x.csv",
+        ] {
+            let cli = Cli::try_parse_from(args.split(' ')).unwrap();
+            assert!(cli.format == Output::Text, "{}", args);
+            let cli = Cli::try_parse_from(args.split(' ').chain(["--output", "json"])).unwrap();
+            assert!(cli.format == Output::Json, "{}", args);
+        }
+    }
+
+    #[test]
+    fn printed_result_is_the_only_json_document() {
+        let cli = Cli::try_parse_from(["bliss", "--output", "json"]).unwrap();
+        assert!(!cli.printed.get());
+        cli.print_json(&json!({ "results": [] }));
+        assert!(cli.printed.get());
+    }
+}
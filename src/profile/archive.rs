@@ -0,0 +1,145 @@
+use std::{collections::{HashMap, HashSet}, fs::{self, File}, io::{Error, ErrorKind, Read}, path::Path};
+use chrono::{DateTime, Local};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use super::local_profile::{LocalProfile, PROFILE_FILENAME, AVATAR_FILENAME, BANNER_FILENAME};
+
+const MANIFEST_FILENAME: &str = "manifest.yml";
+const ARCHIVE_VERSION: u32 = 1;
+const PROFILE_FILES: [&str; 3] = [PROFILE_FILENAME, AVATAR_FILENAME, BANNER_FILENAME];
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    profile_name: String,
+    date_created: DateTime<Local>,
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    name: String,
+    sha256: String,
+}
+
+fn checksum(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn append_file<W: std::io::Write>(builder: &mut tar::Builder<W>, name: &str, bytes: &[u8]) -> Result<(), Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Local::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, bytes)
+}
+
+/// Bundles local profile with its images into single gzipped tarball.
+pub fn export(profile_name: &str, output: &Path) -> Result<(), Error> {
+    if !LocalProfile::exists(profile_name)? {
+        return Err(Error::new(ErrorKind::NotFound, format!("No local profile named {}.", profile_name)));
+    }
+    let dir = LocalProfile::dir(profile_name)?;
+    let mut files = Vec::new();
+    for filename in PROFILE_FILES {
+        let path = dir.join(filename);
+        if path.exists() {
+            files.push((filename, fs::read(path)?));
+        }
+    }
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        profile_name: profile_name.to_owned(),
+        date_created: Local::now(),
+        files: files
+            .iter()
+            .map(|(name, bytes)| ManifestEntry { name: name.to_string(), sha256: checksum(bytes) })
+            .collect(),
+    };
+    let manifest = serde_yaml::to_string(&manifest)
+        .map_err(|err| invalid(format!("Failed to serialize manifest: {}", err)))?;
+
+    let encoder = GzEncoder::new(File::create(output)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    append_file(&mut builder, MANIFEST_FILENAME, manifest.as_bytes())?;
+    for (name, bytes) in files.iter() {
+        append_file(&mut builder, name, bytes)?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Unpacks profile archive after verifying its checksums. Returns name of imported profile.
+pub fn import(archive: &Path, profile_name: Option<&str>, force: bool) -> Result<String, Error> {
+    let mut entries: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut tar = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        if name != MANIFEST_FILENAME && !PROFILE_FILES.contains(&name.as_str()) {
+            return Err(invalid(format!("Unexpected file in archive: {}.", name)));
+        }
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        if entries.insert(name.clone(), bytes).is_some() {
+            return Err(invalid(format!("File {} appears in archive twice.", name)));
+        }
+    }
+
+    let manifest = entries
+        .remove(MANIFEST_FILENAME)
+        .ok_or(invalid("Archive has no manifest.".to_owned()))?;
+    let manifest: Manifest = serde_yaml::from_slice(&manifest)
+        .map_err(|err| invalid(format!("Malformed manifest: {}", err)))?;
+    if manifest.version != ARCHIVE_VERSION {
+        return Err(invalid(format!("Unsupported archive version: {}.", manifest.version)));
+    }
+    if !manifest.files.iter().any(|entry| entry.name == PROFILE_FILENAME) {
+        return Err(invalid(format!("Archive has no {}.", PROFILE_FILENAME)));
+    }
+    let mut listed = HashSet::new();
+    for entry in manifest.files.iter() {
+        if !listed.insert(entry.name.as_str()) {
+            return Err(invalid(format!("File {} is listed in manifest twice.", entry.name)));
+        }
+    }
+    if let Some(name) = entries.keys().find(|name| !listed.contains(name.as_str())) {
+        return Err(invalid(format!("File {} in archive is not listed in manifest.", name)));
+    }
+    for entry in manifest.files.iter() {
+        let bytes = entries
+            .get(&entry.name)
+            .ok_or(invalid(format!("File {} listed in manifest is missing.", entry.name)))?;
+        if checksum(bytes) != entry.sha256 {
+            return Err(invalid(format!("Checksum mismatch for {}.", entry.name)));
+        }
+    }
+
+    let name = profile_name.unwrap_or(&manifest.profile_name);
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(invalid(format!("Invalid profile name: {}.", name)));
+    }
+    if LocalProfile::exists(name)? && !force {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("Local profile {} already exists.", name)));
+    }
+    let dir = LocalProfile::dir(name)?;
+    fs::create_dir_all(&dir)?;
+    for filename in PROFILE_FILES {
+        let path = dir.join(filename);
+        match entries.get(filename) {
+            Some(bytes) => fs::write(path, bytes)?,
+            None if path.exists() => fs::remove_file(path)?,
+            None => {},
+        }
+    }
+    Ok(name.to_owned())
+}
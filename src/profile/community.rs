@@ -1,5 +1,7 @@
 use lemmy_api_common::{lemmy_db_schema::{newtypes::{DbUrl, CommunityId}, source::community}, lemmy_db_views_actor::structs::CommunityView};
 use serde::{Deserialize, Serialize};
+use url::Url;
+use super::actor_name;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Community {
@@ -18,14 +20,26 @@ impl Community {
 
     }
 
-    pub fn is_same(&self, community: &CommunityView) -> bool {
-        if self.name != community.community.name {
-            return false;
-        }
-        if self.actor != community.community.actor_id {
-            return false;
+    /// Creates community known only by its actor url, e.g. `https://lemmy.ml/c/linux`.
+    /// Id is left default as it is resolved on destination instance while pushing.
+    pub fn from_actor(actor: DbUrl) -> Self {
+        Community {
+            name: actor_name(&actor),
+            actor,
+            id: CommunityId::default(),
         }
-        return true;
+    }
+
+    /// RSS feed of community served by its home instance.
+    pub fn feed_url(&self) -> Url {
+        self.actor
+            .join(&format!("/feeds/c/{}.xml", self.name))
+            .unwrap()
+    }
+
+    /// Actor url identifies community across instances, names alone are not unique.
+    pub fn is_same(&self, community: &CommunityView) -> bool {
+        self.actor == community.community.actor_id
     }
 }
 
@@ -37,6 +51,6 @@ impl PartialEq for Community {
         if self.actor != other.actor {
             return false;
         }
-        return true;
+        true
     }
 }
@@ -0,0 +1,55 @@
+use std::io::{Error, ErrorKind};
+use argon2::Argon2;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce, aead::Aead};
+use rand::{RngCore, rngs::OsRng};
+
+const HEADER: &str = "BLISS-ENCRYPTED v1\n";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, Error> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| Error::other(format!("Failed to derive key: {}", err)))?;
+    Ok(key)
+}
+
+pub fn is_encrypted(content: &[u8]) -> bool {
+    content.starts_with(HEADER.as_bytes())
+}
+
+/// Encrypts content with key derived from passphrase.
+/// Output is text: header line followed by base64 of salt, nonce and ciphertext.
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| Error::other("Failed to encrypt profile."))?;
+    let payload = [salt.as_slice(), nonce.as_slice(), ciphertext.as_slice()].concat();
+    Ok(format!("{}{}\n", HEADER, STANDARD.encode(payload)).into_bytes())
+}
+
+pub fn decrypt(content: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_owned());
+    let payload = content
+        .strip_prefix(HEADER.as_bytes())
+        .ok_or(invalid("Profile is not encrypted."))?;
+    let payload = STANDARD
+        .decode(payload.trim_ascii())
+        .map_err(|_| invalid("Encrypted profile is corrupted."))?;
+    if payload.len() < SALT_LEN + NONCE_LEN {
+        return Err(invalid("Encrypted profile is corrupted."));
+    }
+    let (salt, rest) = payload.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid("Wrong passphrase or corrupted profile."))
+}
@@ -0,0 +1,88 @@
+use std::{io::{Error, ErrorKind, Read, Write}, str::FromStr};
+use lemmy_api_common::lemmy_db_schema::newtypes::DbUrl;
+use serde::{Deserialize, Serialize};
+use super::{Info, community::Community, person::Person};
+
+/// List of `Info` that can be exchanged as CSV.
+#[derive(Clone, Copy)]
+pub enum Section {
+    Follows,
+    CommunityBlocks,
+    PersonBlocks,
+}
+
+impl FromStr for Section {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "follows" => Ok(Section::Follows),
+            "community-blocks" => Ok(Section::CommunityBlocks),
+            "person-blocks" => Ok(Section::PersonBlocks),
+            _ => Err(format!("Unknown section \"{}\", expected one of: follows, community-blocks, person-blocks.", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Row {
+    #[serde(default)]
+    name: String,
+    actor: DbUrl,
+}
+
+/// Writes section as CSV with `name` and `actor` columns.
+pub fn export<W: Write>(info: &Info, section: Section, writer: W) -> Result<usize, Error> {
+    let rows: Vec<Row> = match section {
+        Section::Follows => community_rows(&info.communities_follows),
+        Section::CommunityBlocks => community_rows(&info.communities_blocks),
+        Section::PersonBlocks => info.people_blocks
+            .iter()
+            .map(|p| Row { name: p.username.clone(), actor: p.actor.clone() })
+            .collect(),
+    };
+    let mut writer = csv::Writer::from_writer(writer);
+    for row in rows.iter() {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(rows.len())
+}
+
+fn community_rows(communities: &[Community]) -> Vec<Row> {
+    communities
+        .iter()
+        .map(|c| Row { name: c.name.clone(), actor: c.actor.clone() })
+        .collect()
+}
+
+/// Reads CSV into section. Only `actor` column is required, `name` is just a label for humans:
+/// names are always derived from actor and community ids are resolved while pushing.
+/// Entries already in section are kept as they are, with `replace` only those missing from CSV are dropped.
+/// Returns number of added entries.
+pub fn import<R: Read>(info: &mut Info, section: Section, reader: R, replace: bool) -> Result<usize, Error> {
+    let mut actors = Vec::new();
+    for row in csv::Reader::from_reader(reader).deserialize::<Row>() {
+        let row = row.map_err(|err| Error::new(ErrorKind::InvalidData, format!("Malformed CSV: {}", err)))?;
+        actors.push(row.actor);
+    }
+    let added = match section {
+        Section::Follows => merge(&mut info.communities_follows, actors, replace, |c| &c.actor, Community::from_actor),
+        Section::CommunityBlocks => merge(&mut info.communities_blocks, actors, replace, |c| &c.actor, Community::from_actor),
+        Section::PersonBlocks => merge(&mut info.people_blocks, actors, replace, |p| &p.actor, Person::from_actor),
+    };
+    Ok(added)
+}
+
+fn merge<T>(list: &mut Vec<T>, actors: Vec<DbUrl>, replace: bool, actor: fn(&T) -> &DbUrl, from_actor: fn(DbUrl) -> T) -> usize {
+    if replace {
+        list.retain(|item| actors.contains(actor(item)));
+    }
+    let len = list.len();
+    for new in actors {
+        if !list.iter().any(|item| *actor(item) == new) {
+            list.push(from_actor(new));
+        }
+    }
+    list.len() - len
+}
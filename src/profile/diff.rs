@@ -0,0 +1,115 @@
+use std::fmt;
+use serde::Serialize;
+use serde_yaml::Value;
+use super::{Profile, three_way::{display, to_mapping}};
+
+/// Setting or profile field with different values.
+#[derive(Serialize)]
+pub struct FieldChange {
+    pub name: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Actors present only in one of compared lists.
+#[derive(Default, Serialize)]
+pub struct ListChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ListChange {
+    fn new(old: Vec<&str>, new: Vec<&str>) -> Self {
+        let missing = |list: &[&str], other: &[&str]| list
+            .iter()
+            .filter(|actor| !other.contains(actor))
+            .map(|actor| actor.to_string())
+            .collect();
+        ListChange {
+            added: missing(&new, &old),
+            removed: missing(&old, &new),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Changes turning one profile into another, e.g. what push of local profile would do to account.
+#[derive(Serialize)]
+pub struct Diff {
+    pub fields: Vec<FieldChange>,
+    pub follows: ListChange,
+    pub community_blocks: ListChange,
+    pub person_blocks: ListChange,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+            && self.follows.is_empty()
+            && self.community_blocks.is_empty()
+            && self.person_blocks.is_empty()
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.fields.iter() {
+            writeln!(f, "~ {}: {} -> {}", change.name, change.old, change.new)?;
+        }
+        for (name, list) in [("follow", &self.follows), ("community block", &self.community_blocks), ("person block", &self.person_blocks)] {
+            for actor in list.added.iter() {
+                writeln!(f, "+ {} {}", name, actor)?;
+            }
+            for actor in list.removed.iter() {
+                writeln!(f, "- {} {}", name, actor)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Profile {
+    /// Changes from this profile to `other`. Meta, avatar and banner are not compared.
+    pub fn diff(&self, other: &Profile) -> Diff {
+        let old = to_mapping(&self.settings);
+        let new = to_mapping(&other.settings);
+        let mut fields: Vec<FieldChange> = new
+            .iter()
+            .filter_map(|(key, new_value)| {
+                let old_value = old.get(key).unwrap_or(&Value::Null);
+                (old_value != new_value).then(|| FieldChange {
+                    name: key.as_str().unwrap_or_default().to_owned(),
+                    old: display(old_value),
+                    new: display(new_value),
+                })
+            })
+            .collect();
+        for (name, old_value, new_value) in [
+            ("bio", &self.info.bio, &other.info.bio),
+            ("display_name", &self.info.display_name, &other.info.display_name),
+        ] {
+            if old_value != new_value {
+                fields.push(FieldChange {
+                    name: name.to_owned(),
+                    old: display(old_value),
+                    new: display(new_value),
+                });
+            }
+        }
+        Diff {
+            fields,
+            follows: ListChange::new(
+                self.info.communities_follows.iter().map(|c| c.actor.as_str()).collect(),
+                other.info.communities_follows.iter().map(|c| c.actor.as_str()).collect()),
+            community_blocks: ListChange::new(
+                self.info.communities_blocks.iter().map(|c| c.actor.as_str()).collect(),
+                other.info.communities_blocks.iter().map(|c| c.actor.as_str()).collect()),
+            person_blocks: ListChange::new(
+                self.info.people_blocks.iter().map(|p| p.actor.as_str()).collect(),
+                other.info.people_blocks.iter().map(|p| p.actor.as_str()).collect()),
+        }
+    }
+}
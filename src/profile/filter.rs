@@ -0,0 +1,73 @@
+use std::str::FromStr;
+use lemmy_api_common::lemmy_db_schema::newtypes::DbUrl;
+use regex::Regex;
+use super::{Info, actor_name};
+
+/// Pattern matched against actor url of community or person.
+#[derive(Clone)]
+pub enum ActorPattern {
+    /// Instance hosting the actor, e.g. `domain:lemmy.world`.
+    Domain(String),
+    /// Glob on name, e.g. `name:rust*`.
+    Name(glob::Pattern),
+    /// Regex on whole actor url, e.g. `regex:^https://lemmy\.ml/c/`.
+    Regex(Regex),
+}
+
+impl FromStr for ActorPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("domain", domain)) => Ok(ActorPattern::Domain(domain.to_lowercase())),
+            Some(("name", name)) => glob::Pattern::new(name)
+                .map(ActorPattern::Name)
+                .map_err(|err| format!("Invalid glob \"{}\": {}", name, err)),
+            Some(("regex", regex)) => Regex::new(regex)
+                .map(ActorPattern::Regex)
+                .map_err(|err| format!("Invalid regex \"{}\": {}", regex, err)),
+            _ => Err(format!("Unknown pattern \"{}\", expected one of: domain:<instance>, name:<glob>, regex:<regex>.", s)),
+        }
+    }
+}
+
+impl ActorPattern {
+    pub fn matches(&self, actor: &DbUrl) -> bool {
+        match self {
+            ActorPattern::Domain(domain) => actor.host_str().is_some_and(|host| host.eq_ignore_ascii_case(domain)),
+            ActorPattern::Name(pattern) => pattern.matches(&actor_name(actor)),
+            ActorPattern::Regex(regex) => regex.is_match(actor.as_str()),
+        }
+    }
+}
+
+/// Selects follows and blocks to push, the rest is left untouched.
+#[derive(Default, Clone)]
+pub struct Filter {
+    only: Vec<ActorPattern>,
+    skip: Vec<ActorPattern>,
+}
+
+impl Filter {
+    pub fn new(only: &[ActorPattern], skip: &[ActorPattern]) -> Filter {
+        Filter {
+            only: only.to_vec(),
+            skip: skip.to_vec(),
+        }
+    }
+
+    /// Actor has to match any of `only` patterns (if there are some) and none of `skip` ones.
+    pub fn matches(&self, actor: &DbUrl) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|p| p.matches(actor)))
+            && !self.skip.iter().any(|p| p.matches(actor))
+    }
+
+    /// Info with only matching follows and blocks.
+    pub fn apply(&self, info: &Info) -> Info {
+        let mut info = info.clone();
+        info.communities_follows.retain(|c| self.matches(&c.actor));
+        info.communities_blocks.retain(|c| self.matches(&c.actor));
+        info.people_blocks.retain(|p| self.matches(&p.actor));
+        info
+    }
+}
@@ -0,0 +1,169 @@
+use lemmy_api_common::lemmy_db_schema::{SortType, ListingType, newtypes::DbUrl};
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::user::User;
+use super::{Profile, Meta, Info, Settings, community::Community, person::Person};
+
+/// User settings backup as exported by Lemmy (`/api/v3/user/export_settings`).
+#[derive(Serialize, Deserialize, Default)]
+pub struct UserSettingsBackup {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar: Option<DbUrl>,
+    pub banner: Option<DbUrl>,
+    pub matrix_id: Option<String>,
+    pub bot_account: Option<bool>,
+    pub settings: Option<BackupSettings>,
+    #[serde(default)]
+    pub followed_communities: Vec<DbUrl>,
+    #[serde(default)]
+    pub saved_posts: Vec<DbUrl>,
+    #[serde(default)]
+    pub saved_comments: Vec<DbUrl>,
+    #[serde(default)]
+    pub blocked_communities: Vec<DbUrl>,
+    #[serde(default)]
+    pub blocked_users: Vec<DbUrl>,
+    #[serde(default)]
+    pub blocked_instances: Vec<String>,
+}
+
+/// Subset of Lemmy's local user settings that bliss keeps in profile.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct BackupSettings {
+    pub email: Option<String>,
+    pub show_nsfw: Option<bool>,
+    pub theme: Option<String>,
+    pub default_sort_type: Option<SortType>,
+    pub default_listing_type: Option<ListingType>,
+    pub interface_language: Option<String>,
+    pub show_avatars: Option<bool>,
+    pub send_notifications_to_email: Option<bool>,
+    pub show_scores: Option<bool>,
+    pub show_bot_accounts: Option<bool>,
+    pub show_read_posts: Option<bool>,
+    pub show_new_post_notifs: Option<bool>,
+    pub open_links_in_new_tab: Option<bool>,
+}
+
+impl UserSettingsBackup {
+    /// Items of backup that have no counterpart in profile.
+    pub fn unsupported(&self) -> Vec<String> {
+        let mut unsupported = Vec::new();
+        if !self.saved_posts.is_empty() {
+            unsupported.push(format!("{} saved posts", self.saved_posts.len()));
+        }
+        if !self.saved_comments.is_empty() {
+            unsupported.push(format!("{} saved comments", self.saved_comments.len()));
+        }
+        if !self.blocked_instances.is_empty() {
+            unsupported.push(format!("{} blocked instances", self.blocked_instances.len()));
+        }
+        unsupported
+    }
+}
+
+impl Settings {
+    /// Missing values fall back to defaults of new Lemmy account.
+    fn from_backup(backup: &UserSettingsBackup) -> Self {
+        let default = BackupSettings::default();
+        let settings = backup.settings.as_ref().unwrap_or(&default);
+        Settings {
+            default_sort_type: settings.default_sort_type.unwrap_or(SortType::Active),
+            default_listing_type: settings.default_listing_type.unwrap_or(ListingType::Local),
+            theme: settings.theme.clone().unwrap_or("browser".to_owned()),
+            interface_language: settings.interface_language.clone().unwrap_or("browser".to_owned()),
+            email: settings.email.clone(),
+            matrix_user_id: backup.matrix_id.clone(),
+            show_nsfw: settings.show_nsfw.unwrap_or(false),
+            show_scores: settings.show_scores.unwrap_or(true),
+            show_avatars: settings.show_avatars.unwrap_or(true),
+            show_bot_accounts: settings.show_bot_accounts.unwrap_or(true),
+            show_read_posts: settings.show_read_posts.unwrap_or(true),
+            show_new_post_notifs: settings.show_new_post_notifs.unwrap_or(false),
+            open_links_in_new_tab: settings.open_links_in_new_tab.unwrap_or(false),
+            send_notifications_to_email: settings.send_notifications_to_email.unwrap_or(false),
+            bot_account: backup.bot_account.unwrap_or(false),
+            discussion_languages: Vec::new(),
+        }
+    }
+}
+
+impl Profile {
+    /// Backup does not identify its owner, so account has to be given explicitly.
+    pub fn from_backup<T>(user: User<T>, backup: &UserSettingsBackup) -> Self {
+        let info = Info {
+            communities_blocks: backup.blocked_communities
+                .iter()
+                .map(|actor| Community::from_actor(actor.clone()))
+                .collect(),
+            communities_follows: backup.followed_communities
+                .iter()
+                .map(|actor| Community::from_actor(actor.clone()))
+                .collect(),
+            people_blocks: backup.blocked_users
+                .iter()
+                .map(|actor| Person::from_actor(actor.clone()))
+                .collect(),
+            bio: backup.bio.clone(),
+            display_name: backup.display_name.clone(),
+            avatar: backup.avatar.as_ref().map(|url| url.to_string()),
+            banner: backup.banner.as_ref().map(|url| url.to_string()),
+        };
+        Profile {
+            meta: Meta::from(user),
+            info,
+            settings: Settings::from_backup(backup),
+        }
+    }
+}
+
+impl From<&Profile> for UserSettingsBackup {
+    fn from(profile: &Profile) -> Self {
+        let settings = &profile.settings;
+        UserSettingsBackup {
+            display_name: profile.info.display_name.clone(),
+            bio: profile.info.bio.clone(),
+            avatar: image_url(&profile.info.avatar),
+            banner: image_url(&profile.info.banner),
+            matrix_id: settings.matrix_user_id.clone(),
+            bot_account: Some(settings.bot_account),
+            settings: Some(BackupSettings {
+                email: settings.email.clone(),
+                show_nsfw: Some(settings.show_nsfw),
+                theme: Some(settings.theme.clone()),
+                default_sort_type: Some(settings.default_sort_type),
+                default_listing_type: Some(settings.default_listing_type),
+                interface_language: Some(settings.interface_language.clone()),
+                show_avatars: Some(settings.show_avatars),
+                send_notifications_to_email: Some(settings.send_notifications_to_email),
+                show_scores: Some(settings.show_scores),
+                show_bot_accounts: Some(settings.show_bot_accounts),
+                show_read_posts: Some(settings.show_read_posts),
+                show_new_post_notifs: Some(settings.show_new_post_notifs),
+                open_links_in_new_tab: Some(settings.open_links_in_new_tab),
+            }),
+            followed_communities: profile.info.communities_follows
+                .iter()
+                .map(|community| community.actor.clone())
+                .collect(),
+            blocked_communities: profile.info.communities_blocks
+                .iter()
+                .map(|community| community.actor.clone())
+                .collect(),
+            blocked_users: profile.info.people_blocks
+                .iter()
+                .map(|person| person.actor.clone())
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Image urls in profile are kept as given by instance, ones that do not parse are left out.
+fn image_url(url: &Option<String>) -> Option<DbUrl> {
+    url.as_deref()
+        .and_then(|url| Url::parse(url).ok())
+        .map(|url| url.into())
+}
@@ -1,17 +1,20 @@
 use std::{path::{PathBuf, Path}, io::{Error, Write, ErrorKind, Read}, fs::{create_dir_all, File, self}};
-use super::Profile;
+use super::{Profile, crypto};
+use crate::bliss::util::get_passphrase;
 use bytes::Bytes;
 use home::home_dir;
 
 
-const PROFILE_PATH_RELATIVE: &'static str = ".bliss/profiles/";
-const PROFILE_FILENAME: &'static str = "profile.yml";
-const AVATAR_FILENAME: &'static str = "avatar.png";
-const BANNER_FILENAME: &'static str = "banner.png";
+const PROFILE_PATH_RELATIVE: &str = ".bliss/profiles/";
+pub(super) const PROFILE_FILENAME: &str = "profile.yml";
+pub(super) const AVATAR_FILENAME: &str = "avatar.png";
+pub(super) const BANNER_FILENAME: &str = "banner.png";
+const SYNC_BASE_FILENAME: &str = "sync_base.yml";
 
 pub struct LocalProfile {
     pub name: String,
     pub profile: Profile,
+    passphrase: Option<String>,
 }
 
 impl LocalProfile {
@@ -19,9 +22,25 @@ impl LocalProfile {
         LocalProfile {
             name: name.to_owned(),
             profile,
+            passphrase: None,
         }
     }
 
+    pub fn is_encrypted(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    /// Rewrites profile file and its sync base with new passphrase, `None` stores them in plaintext.
+    pub fn change_passphrase(&mut self, passphrase: Option<String>) -> Result<(), Error> {
+        let sync_base = self.load_sync_base()?;
+        self.passphrase = passphrase;
+        self.write()?;
+        if let Some(base) = sync_base {
+            self.save_sync_base(&base)?;
+        }
+        Ok(())
+    }
+
     pub fn save_avatar(&self, avatar: Option<Bytes>) -> Result<bool, Error> {
         self.save_image(avatar, AVATAR_FILENAME, "avatar")
     }
@@ -35,14 +54,26 @@ impl LocalProfile {
             return Ok(false);
         }
         let image = image::load_from_memory(&image.unwrap())
-            .map_err(|err| Error::new(ErrorKind::Other, format!("Failed to load image {}; : {}.", debug_name, err)))?;
+            .map_err(|err| Error::other(format!("Failed to load image {}; : {}.", debug_name, err)))?;
         let path = Self::path(&self.name, filename)?;
         let path = Path::new(&path);
-        image.save(&path)
-            .map_err(|err| Error::new(ErrorKind::Other, format!("Failed to save image; {}", err)))?;
+        image.save(path)
+            .map_err(|err| Error::other(format!("Failed to save image; {}", err)))?;
         Ok(true)
     }
 
+    pub fn profile_path(&self) -> Result<PathBuf, Error> {
+        Self::path(&self.name, PROFILE_FILENAME)
+    }
+
+    pub fn avatar_path(&self) -> Result<PathBuf, Error> {
+        Self::path(&self.name, AVATAR_FILENAME)
+    }
+
+    pub fn banner_path(&self) -> Result<PathBuf, Error> {
+        Self::path(&self.name, BANNER_FILENAME)
+    }
+
     pub fn load_avatar(&self) -> Result<Option<Vec<u8>>, Error> {
         self.load_image(AVATAR_FILENAME)
     }
@@ -62,37 +93,95 @@ impl LocalProfile {
         Ok(Some(bytes))
     }
 
-    fn path(profile_name: &str, filename: &str) -> Result<PathBuf, Error> {
-        let mut path = home_dir()
+    pub(super) fn dir(profile_name: &str) -> Result<PathBuf, Error> {
+        let path = home_dir()
             .ok_or(Error::new(ErrorKind::NotFound, "Impossible to get home directory."))?
             .join(PROFILE_PATH_RELATIVE)
-            .join(&profile_name);
+            .join(profile_name);
+        Ok(path)
+    }
+
+    fn path(profile_name: &str, filename: &str) -> Result<PathBuf, Error> {
+        let mut path = Self::dir(profile_name)?;
         create_dir_all(&path)?;
         path.push(filename);
         Ok(path)
     }
 
+    pub fn exists(profile_name: &str) -> Result<bool, Error> {
+        let path = Self::dir(profile_name)?.join(PROFILE_FILENAME);
+        Ok(path.exists())
+    }
+
+    /// Loads profile, encrypted one with passphrase from `BLISS_PROFILE_PW` or prompt.
     pub fn load(profile_name: &str) -> Result<LocalProfile, Error> {
-        let path = Self::path(&profile_name, PROFILE_FILENAME)?;
-        let profile = fs::read_to_string(path)?;
-        let profile = serde_yaml::from_str::<Profile>(&profile)
-            .expect(&format!("Could not read current profile: {}.", profile_name));
+        Self::load_with(profile_name, None)
+    }
+
+    /// Loads profile decrypting it with given passphrase, falls back to `load` behaviour if none is given.
+    pub fn load_with(profile_name: &str, passphrase: Option<&str>) -> Result<LocalProfile, Error> {
+        let (profile, passphrase) = Self::read_profile(profile_name, PROFILE_FILENAME, passphrase)?;
         let lp = LocalProfile {
             name: profile_name.to_owned(),
             profile,
+            passphrase,
         };
         Ok(lp)
     }
 
+    fn read_profile(profile_name: &str, filename: &str, passphrase: Option<&str>) -> Result<(Profile, Option<String>), Error> {
+        let path = Self::path(profile_name, filename)?;
+        let mut profile = fs::read(path)?;
+        let mut passphrase = passphrase.map(str::to_owned);
+        if crypto::is_encrypted(&profile) {
+            let pw = passphrase.unwrap_or_else(|| get_passphrase(profile_name, false));
+            profile = crypto::decrypt(&profile, &pw)?;
+            passphrase = Some(pw);
+        } else {
+            passphrase = None;
+        }
+        let profile = serde_yaml::from_slice::<Profile>(&profile)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, format!("Malformed profile {}: {}", profile_name, err)))?;
+        Ok((profile, passphrase))
+    }
+
+    /// Last state both accounts agreed on after sync, if they were synced before.
+    pub fn load_sync_base(&self) -> Result<Option<Profile>, Error> {
+        if !Self::dir(&self.name)?.join(SYNC_BASE_FILENAME).exists() {
+            return Ok(None);
+        }
+        let (profile, _) = Self::read_profile(&self.name, SYNC_BASE_FILENAME, self.passphrase.as_deref())?;
+        Ok(Some(profile))
+    }
+
+    pub fn save_sync_base(&self, base: &Profile) -> Result<(), Error> {
+        self.write_profile(base, SYNC_BASE_FILENAME)
+    }
+
     pub fn save(&mut self) -> Result<(), Error> {
-        let path = Self::path(&self.name, PROFILE_FILENAME)?;
-        let prev_profile = LocalProfile::load(&self.name);
-        if prev_profile.is_ok() {
-            self.profile.sync(prev_profile.unwrap().profile);
+        if Self::exists(&self.name)? {
+            let prev_profile = LocalProfile::load_with(&self.name, self.passphrase.as_deref())?;
+            if self.passphrase.is_none() {
+                self.passphrase = prev_profile.passphrase;
+            }
+            self.profile.sync(prev_profile.profile);
+        }
+        self.write()
+    }
+
+    /// Writes profile file as is, encrypting it if passphrase is set.
+    pub fn write(&self) -> Result<(), Error> {
+        self.write_profile(&self.profile, PROFILE_FILENAME)
+    }
+
+    fn write_profile(&self, profile: &Profile, filename: &str) -> Result<(), Error> {
+        let path = Self::path(&self.name, filename)?;
+        let mut profile = serde_yaml::to_string(profile).unwrap().into_bytes();
+        if let Some(passphrase) = &self.passphrase {
+            profile = crypto::encrypt(&profile, passphrase)?;
         }
-        let profile = serde_yaml::to_string(&self.profile).unwrap(); 
         let mut file = File::create(path)?;
-        file.write_all(profile.as_bytes())?;
+        file.write_all(&profile)?;
         Ok(())
     }
 }
@@ -1,14 +1,32 @@
 use chrono::{DateTime, Local};
-use lemmy_api_common::{site::GetSiteResponse, lemmy_db_schema::{SortType, ListingType, newtypes::LanguageId}, person::SaveUserSettings, sensitive::Sensitive};
+use lemmy_api_common::{site::GetSiteResponse, lemmy_db_schema::{SortType, ListingType, newtypes::{LanguageId, DbUrl}}, person::SaveUserSettings, sensitive::Sensitive};
 use serde::{Serialize, Deserialize};
 
 use crate::user::User;
 
-use self::{community::Community, person::Person};
+use self::{community::Community, person::Person, parameters::Parameters};
 
 pub mod community;
 pub mod person;
 pub mod local_profile;
+pub mod archive;
+mod crypto;
+pub mod lemmy_backup;
+pub mod opml;
+pub mod csv_lists;
+pub mod three_way;
+pub mod parameters;
+pub mod filter;
+pub mod diff;
+
+/// Name of community or person is last segment of its actor url.
+fn actor_name(actor: &DbUrl) -> String {
+    actor
+        .path_segments()
+        .and_then(|segments| segments.rev().find(|s| !s.is_empty()))
+        .unwrap_or_default()
+        .to_owned()
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 struct Meta {
@@ -73,6 +91,20 @@ impl Info {
         }
     }
 
+    /// Adds follows and blocks of other info that are missing in this one.
+    pub fn union(&mut self, other: &Self) {
+        fn extend<T: PartialEq + Clone>(list: &mut Vec<T>, other: &[T]) {
+            for item in other.iter() {
+                if !list.contains(item) {
+                    list.push(item.clone());
+                }
+            }
+        }
+        extend(&mut self.communities_blocks, &other.communities_blocks);
+        extend(&mut self.communities_follows, &other.communities_follows);
+        extend(&mut self.people_blocks, &other.people_blocks);
+    }
+
     pub fn subtract(&self, other: &Self) -> Self {
         let com_block = self.communities_blocks
             .clone()
@@ -171,21 +203,48 @@ impl Profile {
         }
     }
 
+    /// Account the profile was pulled from.
+    pub fn user(&self) -> User {
+        User::new(&self.meta.username, &self.meta.instance)
+    }
+
+    pub fn account_name(&self) -> String {
+        format!("{}@{}", self.meta.username, self.meta.instance.host_str().unwrap_or_default())
+    }
+
+    /// Unites follows and blocks of all profiles, everything else is taken from primary.
+    pub fn merge(primary: &Profile, others: &[&Profile]) -> Profile {
+        let mut merged = primary.clone();
+        for other in others.iter() {
+            merged.info.union(&other.info);
+        }
+        merged
+    }
+
+    /// Compares everything but meta.
+    pub fn same_content(&self, other: &Profile) -> bool {
+        let content = |p: &Profile| serde_yaml::to_string(&(&p.info, &p.settings)).unwrap();
+        content(self) == content(other)
+    }
+
     pub fn sync(&mut self, other: Self) {
         self.meta = other.meta;
         self.meta.touch();
     }
 
-    pub fn ignore_parameters(mut self, exclude: &[String]) -> Self {
-        for param_name in exclude.iter() {
-            info!("Ignoring parameter \"{}\"...", param_name);
-            match param_name.as_str() {
-                "email" => self.settings.email = None,
-                "matrix_user_id" => self.settings.matrix_user_id = None,
-                "bio" => self.info.bio = None,
-                "display_name" => self.info.display_name = None,
-                _ => error!("Unknown parameter \"{}\". Skipping.", param_name),
-            }
+    /// Clears excluded optional fields, so they take no part in comparisons.
+    pub fn ignore_parameters(mut self, parameters: &Parameters) -> Self {
+        if parameters.excludes("email") {
+            self.settings.email = None;
+        }
+        if parameters.excludes("matrix_user_id") {
+            self.settings.matrix_user_id = None;
+        }
+        if parameters.excludes("bio") {
+            self.info.bio = None;
+        }
+        if parameters.excludes("display_name") {
+            self.info.display_name = None;
         }
         self
     }
@@ -199,10 +258,7 @@ impl From<Profile> for SaveUserSettings {
             default_listing_type: Some(profile.settings.default_listing_type),
             theme: Some(profile.settings.theme),
             interface_language: Some(profile.settings.interface_language),
-            email: match profile.settings.email {
-                Some(email) => Some(Sensitive::new(email)),
-                None => None,
-            },
+            email: profile.settings.email.map(Sensitive::new),
             matrix_user_id: profile.settings.matrix_user_id,
             show_nsfw: Some(profile.settings.show_nsfw),
             show_scores: Some(profile.settings.show_scores),
@@ -0,0 +1,46 @@
+use chrono::Local;
+use url::Url;
+use super::Profile;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn outline(title: &str, feed: &Url, html: Option<&Url>) -> String {
+    let html = html
+        .map(|url| format!(" htmlUrl=\"{}\"", escape(url.as_str())))
+        .unwrap_or_default();
+    format!("    <outline type=\"rss\" text=\"{title}\" title=\"{title}\" xmlUrl=\"{}\"{}/>\n",
+        escape(feed.as_str()), html, title = escape(title))
+}
+
+/// Renders OPML document with RSS feeds of followed communities.
+/// Front page feed url contains user token, so it is only added when given explicitly.
+pub fn opml(profile: &Profile, front_page: Option<&Url>) -> String {
+    let mut body = String::new();
+    if let Some(feed) = front_page {
+        body.push_str(&outline("Subscribed front page", feed, None));
+    }
+    for community in profile.info.communities_follows.iter() {
+        let title = format!("{}@{}", community.name, community.actor.host_str().unwrap_or_default());
+        body.push_str(&outline(&title, &community.feed_url(), Some(&community.actor)));
+    }
+    format!(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<opml version=\"2.0\">\n",
+            "  <head>\n",
+            "    <title>{}</title>\n",
+            "    <dateCreated>{}</dateCreated>\n",
+            "  </head>\n",
+            "  <body>\n",
+            "{}",
+            "  </body>\n",
+            "</opml>\n"),
+        escape(&format!("Lemmy communities of {}", profile.account_name())),
+        Local::now().to_rfc2822(),
+        body)
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeSet;
+use glob::Pattern;
+use lemmy_api_common::person::SaveUserSettings;
+use super::filter::Filter;
+
+/// Fields pushed through user settings.
+const SETTINGS: &[&str] = &[
+    "default_sort_type",
+    "default_listing_type",
+    "theme",
+    "interface_language",
+    "email",
+    "matrix_user_id",
+    "show_nsfw",
+    "show_scores",
+    "show_avatars",
+    "show_bot_accounts",
+    "show_read_posts",
+    "show_new_post_notifs",
+    "open_links_in_new_tab",
+    "send_notifications_to_email",
+    "bot_account",
+    "discussion_languages",
+    "bio",
+    "display_name",
+];
+
+/// Lists pushed item by item.
+const SECTIONS: &[&str] = &["follows", "community_blocks", "person_blocks"];
+
+/// Uploaded only when included.
+const IMAGES: &[&str] = &["avatar", "banner"];
+
+const GROUPS: &[(&str, &[&str])] = &[
+    ("settings", SETTINGS),
+    ("sections", SECTIONS),
+    ("blocks", &["community_blocks", "person_blocks"]),
+    ("images", IMAGES),
+    ("notifications", &["show_new_post_notifs", "send_notifications_to_email"]),
+    ("display", &["theme", "interface_language", "show_nsfw", "show_scores", "show_avatars",
+        "show_bot_accounts", "show_read_posts", "open_links_in_new_tab"]),
+    ("listing", &["default_sort_type", "default_listing_type", "discussion_languages"]),
+    ("identity", &["email", "matrix_user_id", "bio", "display_name", "bot_account"]),
+];
+
+fn all() -> impl Iterator<Item = &'static str> {
+    SETTINGS.iter().chain(SECTIONS).chain(IMAGES).copied()
+}
+
+/// Parameter names matched by field name, group name or wildcard like `show_*`.
+fn expand(name: &str) -> Result<Vec<&'static str>, String> {
+    let name = name.replace('-', "_");
+    if let Some((_, fields)) = GROUPS.iter().find(|(group, _)| *group == name) {
+        return Ok(fields.to_vec());
+    }
+    let pattern = Pattern::new(&name)
+        .map_err(|err| format!("Invalid parameter pattern \"{}\": {}", name, err))?;
+    let matched: Vec<_> = all().filter(|field| pattern.matches(field)).collect();
+    match matched.is_empty() {
+        true => Err(format!("Unknown parameter \"{}\". {}", name, valid_names())),
+        false => Ok(matched),
+    }
+}
+
+fn valid_names() -> String {
+    let groups: Vec<_> = GROUPS.iter().map(|(group, _)| *group).collect();
+    format!("Valid parameters: {}. Groups: {}.", all().collect::<Vec<_>>().join(", "), groups.join(", "))
+}
+
+/// Which parameters of profile get pushed.
+#[derive(Default, Clone)]
+pub struct Parameters {
+    excluded: BTreeSet<&'static str>,
+    included: BTreeSet<&'static str>,
+    filter: Filter,
+}
+
+impl Parameters {
+    /// Validates all names, only images can be included.
+    pub fn new(exclude: &[String], include: &[String]) -> Result<Parameters, String> {
+        let mut parameters = Parameters::default();
+        for name in exclude.iter() {
+            parameters.excluded.extend(expand(name)?);
+        }
+        for name in include.iter() {
+            for field in expand(name)? {
+                if !IMAGES.contains(&field) {
+                    return Err(format!("Parameter \"{}\" is always pushed, only {} can be included.", field, IMAGES.join(", ")));
+                }
+                parameters.included.insert(field);
+            }
+        }
+        Ok(parameters)
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Parameters {
+        self.filter = filter;
+        self
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    pub fn excluded(&self) -> impl Iterator<Item = &&'static str> {
+        self.excluded.iter()
+    }
+
+    pub fn excludes(&self, name: &str) -> bool {
+        self.excluded.contains(name)
+    }
+
+    pub fn includes(&self, name: &str) -> bool {
+        self.included.contains(name) && !self.excludes(name)
+    }
+
+    /// True if there is nothing left to send through user settings.
+    pub fn excludes_settings(&self) -> bool {
+        SETTINGS.iter().all(|field| self.excludes(field))
+            && IMAGES.iter().all(|field| !self.includes(field))
+    }
+
+    /// Unsets excluded fields and images not included, so that server keeps its values.
+    /// Image urls in profile may point to another instance.
+    pub fn apply(&self, settings: &mut SaveUserSettings) {
+        for field in self.excluded.iter() {
+            match *field {
+                "default_sort_type" => settings.default_sort_type = None,
+                "default_listing_type" => settings.default_listing_type = None,
+                "theme" => settings.theme = None,
+                "interface_language" => settings.interface_language = None,
+                "email" => settings.email = None,
+                "matrix_user_id" => settings.matrix_user_id = None,
+                "show_nsfw" => settings.show_nsfw = None,
+                "show_scores" => settings.show_scores = None,
+                "show_avatars" => settings.show_avatars = None,
+                "show_bot_accounts" => settings.show_bot_accounts = None,
+                "show_read_posts" => settings.show_read_posts = None,
+                "show_new_post_notifs" => settings.show_new_post_notifs = None,
+                "open_links_in_new_tab" => settings.open_links_in_new_tab = None,
+                "send_notifications_to_email" => settings.send_notifications_to_email = None,
+                "bot_account" => settings.bot_account = None,
+                "discussion_languages" => settings.discussion_languages = None,
+                "bio" => settings.bio = None,
+                "display_name" => settings.display_name = None,
+                "avatar" => settings.avatar = None,
+                "banner" => settings.banner = None,
+                _ => {},
+            }
+        }
+        if !self.includes("avatar") {
+            settings.avatar = None;
+        }
+        if !self.includes("banner") {
+            settings.banner = None;
+        }
+    }
+}
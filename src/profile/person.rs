@@ -1,5 +1,6 @@
 use lemmy_api_common::{lemmy_db_schema::{newtypes::DbUrl, source::person}, lemmy_db_views_actor::structs::PersonView};
 use serde::{Serialize, Deserialize};
+use super::actor_name;
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Person {
@@ -15,13 +16,16 @@ impl Person {
         }
     }
 
-    pub fn is_same(&self, person: &PersonView) -> bool {
-        if self.username != person.person.name {
-            return false;
-        }
-        if self.actor != person.person.actor_id {
-            return false;
+    /// Creates person known only by its actor url, e.g. `https://lemmy.ml/u/alice`.
+    pub fn from_actor(actor: DbUrl) -> Self {
+        Person {
+            username: actor_name(&actor),
+            actor,
         }
-        return true;
+    }
+
+    /// Actor url identifies person across instances, names alone are not unique.
+    pub fn is_same(&self, person: &PersonView) -> bool {
+        self.actor == person.person.actor_id
     }
 }
@@ -0,0 +1,190 @@
+use std::{io::{stdin, stderr, BufRead, Write}, str::FromStr};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_yaml::{Mapping, Value};
+use super::{Profile, Info, Settings};
+
+/// How to settle field changed differently on both sides since last sync.
+#[derive(Clone, Copy)]
+pub enum ConflictPolicy {
+    PreferLeft,
+    PreferRight,
+    Prompt,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefer-left" => Ok(ConflictPolicy::PreferLeft),
+            "prefer-right" => Ok(ConflictPolicy::PreferRight),
+            "prompt" => Ok(ConflictPolicy::Prompt),
+            _ => Err(format!("Unknown conflict policy \"{}\", expected one of: prefer-left, prefer-right, prompt.", s)),
+        }
+    }
+}
+
+pub(super) fn display<T: Serialize>(value: &T) -> String {
+    serde_yaml::to_string(value)
+        .unwrap_or_default()
+        .trim()
+        .to_owned()
+}
+
+fn resolve<T: Serialize + Clone>(name: &str, left: &T, right: &T, policy: ConflictPolicy) -> T {
+    let choice = match policy {
+        ConflictPolicy::PreferLeft => true,
+        ConflictPolicy::PreferRight => false,
+        ConflictPolicy::Prompt => ask(name, left, right, &mut stdin().lock()),
+    };
+    let chosen = if choice { left } else { right };
+    info!("Conflict in {} resolved to {}.", name, display(chosen));
+    chosen.clone()
+}
+
+/// Asks until answer is left or right, true for left.
+fn ask<T: Serialize>(name: &str, left: &T, right: &T, input: &mut impl BufRead) -> bool {
+    loop {
+        eprint!("Conflict in {}: [l]eft = {}, [r]ight = {}? ", name, display(left), display(right));
+        stderr().flush().unwrap();
+        let mut answer = String::new();
+        input.read_line(&mut answer).unwrap();
+        match answer.trim() {
+            "l" | "left" => break true,
+            "r" | "right" => break false,
+            _ => continue,
+        }
+    }
+}
+
+/// Side that changed since base wins, changes made on both sides are conflicts.
+fn merge_field<T: Serialize + Clone + PartialEq>(name: &str, base: Option<&T>, left: &T, right: &T, policy: ConflictPolicy) -> T {
+    if left == right {
+        return left.clone();
+    }
+    match base {
+        Some(base) if base == left => right.clone(),
+        Some(base) if base == right => left.clone(),
+        _ => resolve(name, left, right, policy),
+    }
+}
+
+/// Keeps everything present on either side, except what was in base and got removed on one side.
+fn merge_list<T: Clone + PartialEq>(base: Option<&Vec<T>>, left: &[T], right: &[T]) -> Vec<T> {
+    let removed = |item: &T| match base {
+        Some(base) => base.contains(item) && !(left.contains(item) && right.contains(item)),
+        None => false,
+    };
+    let mut merged: Vec<T> = Vec::new();
+    for item in left.iter().chain(right.iter()) {
+        if !removed(item) && !merged.contains(item) {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+pub(super) fn to_mapping<T: Serialize>(value: &T) -> Mapping {
+    match serde_yaml::to_value(value) {
+        Ok(Value::Mapping(mapping)) => mapping,
+        _ => Mapping::new(),
+    }
+}
+
+fn merge_struct<T: Serialize + DeserializeOwned>(base: Option<&T>, left: &T, right: &T, policy: ConflictPolicy) -> T {
+    let base = base.map(to_mapping);
+    let left = to_mapping(left);
+    let right = to_mapping(right);
+    let mut merged = Mapping::new();
+    for (key, left_value) in left.iter() {
+        let right_value = right.get(key).unwrap_or(&Value::Null);
+        let base_value = base.as_ref().and_then(|base| base.get(key));
+        let name = key.as_str().unwrap_or_default();
+        merged.insert(key.clone(), merge_field(name, base_value, left_value, right_value, policy));
+    }
+    serde_yaml::from_value(Value::Mapping(merged)).unwrap()
+}
+
+impl Profile {
+    /// Three-way merge of two accounts against their last common state.
+    /// Without base, lists are united and every differing field is a conflict.
+    /// Meta is taken from left side.
+    pub fn three_way_merge(base: Option<&Profile>, left: &Profile, right: &Profile, policy: ConflictPolicy) -> Profile {
+        let base_info = base.map(|b| &b.info);
+        let info = Info {
+            communities_blocks: merge_list(base_info.map(|i| &i.communities_blocks), &left.info.communities_blocks, &right.info.communities_blocks),
+            communities_follows: merge_list(base_info.map(|i| &i.communities_follows), &left.info.communities_follows, &right.info.communities_follows),
+            people_blocks: merge_list(base_info.map(|i| &i.people_blocks), &left.info.people_blocks, &right.info.people_blocks),
+            bio: merge_field("bio", base_info.map(|i| &i.bio), &left.info.bio, &right.info.bio, policy),
+            display_name: merge_field("display_name", base_info.map(|i| &i.display_name), &left.info.display_name, &right.info.display_name, policy),
+            avatar: None,
+            banner: None,
+        };
+        let settings: Settings = merge_struct(base.map(|b| &b.settings), &left.settings, &right.settings, policy);
+        Profile {
+            meta: left.meta.clone(),
+            info,
+            settings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use serde::{Deserialize, Serialize};
+    use crate::{User, profile::{Profile, community::Community, lemmy_backup::UserSettingsBackup}};
+    use super::*;
+
+    fn community(name: &str) -> Community {
+        Community::from_actor(url::Url::parse(&format!("https://lemmy.ml/c/{}", name)).unwrap().into())
+    }
+
+    fn profile(follows: &[&str]) -> Profile {
+        let user = User::new("alice", &"https://lemmy.ml".parse().unwrap());
+        let mut profile = Profile::from_backup(user, &UserSettingsBackup::default());
+        profile.info.communities_follows = follows.iter().map(|name| community(name)).collect();
+        profile
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Fields {
+        theme: String,
+        show_nsfw: bool,
+    }
+
+    #[test]
+    fn without_base_lists_are_united() {
+        let merged = Profile::three_way_merge(None, &profile(&["rust", "linux"]), &profile(&["linux", "news"]), ConflictPolicy::PreferLeft);
+
+        assert_eq!(merged.info.communities_follows, [community("rust"), community("linux"), community("news")]);
+    }
+
+    #[test]
+    fn removal_on_one_side_is_kept() {
+        let base = vec![1, 2, 3];
+
+        assert_eq!(merge_list(Some(&base), &[1, 3], &[1, 2, 3, 4]), [1, 3, 4]);
+    }
+
+    #[test]
+    fn change_on_both_sides_follows_policy() {
+        let base = 1;
+
+        assert_eq!(merge_field("value", Some(&base), &2, &3, ConflictPolicy::PreferLeft), 2);
+        assert_eq!(merge_field("value", Some(&base), &2, &3, ConflictPolicy::PreferRight), 3);
+        assert!(!ask("value", &2, &3, &mut Cursor::new("what\nr\n")));
+        assert!(ask("value", &2, &3, &mut Cursor::new("left\n")));
+    }
+
+    #[test]
+    fn struct_field_changed_on_one_side_wins() {
+        let base = Fields { theme: "browser".to_owned(), show_nsfw: false };
+        let left = Fields { theme: "darkly".to_owned(), show_nsfw: false };
+        let right = Fields { theme: "browser".to_owned(), show_nsfw: true };
+
+        let merged: Fields = merge_struct(Some(&base), &left, &right, ConflictPolicy::PreferRight);
+
+        assert_eq!(merged, Fields { theme: "darkly".to_owned(), show_nsfw: true });
+    }
+}
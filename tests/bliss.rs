@@ -0,0 +1,602 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use bliss::{Bliss, Error, User, bliss::{audit::{Audit, Cause}, report::Action, sync::{self, Remote}, util::PasswordSource}, lemmy::{api::Api, client::ClientConfig}, profile::{Profile, archive, lemmy_backup::UserSettingsBackup, local_profile::LocalProfile, parameters::Parameters}};
+use common::{MockLemmy, png, profile_dir, run, write_file};
+
+const REMOTE: &str = "https://lemmy.ml";
+
+async fn connect(mock: &MockLemmy, profile_name: &str) -> Bliss {
+    connect_with(mock, profile_name, &ClientConfig::default()).await
+}
+
+async fn connect_with(mock: &MockLemmy, profile_name: &str, config: &ClientConfig) -> Bliss {
+    let (user, password) = {
+        let state = mock.state();
+        (User::new(&state.username, &state.url), state.password.clone())
+    };
+    let api = Api::with_config(config).unwrap();
+    Bliss::new_with(api, user, password, None, profile_name).await.unwrap()
+}
+
+fn fast_retries(max_attempts: u32) -> ClientConfig {
+    ClientConfig {
+        max_attempts: Some(max_attempts),
+        retry_delay: Some(1),
+        ..Default::default()
+    }
+}
+
+/// Account with two follows, one blocked community and one blocked person.
+fn source_account() -> MockLemmy {
+    let mock = MockLemmy::start("alice", "secret");
+    let rust = mock.add_community("rust", REMOTE);
+    let linux = mock.add_community("linux", REMOTE);
+    let memes = mock.add_community("memes", REMOTE);
+    let troll = mock.add_person("troll", REMOTE);
+    let mut state = mock.state();
+    state.follows = vec![rust, linux];
+    state.community_blocks = vec![memes];
+    state.person_blocks = vec![troll];
+    state.bio = Some("Hello".to_owned());
+    drop(state);
+    mock
+}
+
+/// Empty account on instance knowing the same communities and people as source one.
+fn destination_account() -> MockLemmy {
+    let mock = MockLemmy::start("alice", "other");
+    for name in ["rust", "linux", "memes", "news"] {
+        mock.add_community(name, REMOTE);
+    }
+    mock.add_person("troll", REMOTE);
+    mock
+}
+
+async fn pull(mock: &MockLemmy, profile_name: &str) {
+    connect(mock, profile_name).await.pull().await.unwrap();
+}
+
+#[test]
+fn pull_saves_profile_and_avatar() {
+    run(async {
+        let mock = source_account();
+        let avatar = png([255, 0, 0]);
+        mock.state().images.insert("me.png".to_owned(), avatar);
+        let avatar_url = mock.url().join("/pictrs/image/me.png").unwrap();
+        mock.state().avatar = Some(avatar_url.to_string());
+
+        let summary = connect(&mock, "pull").await.pull().await.unwrap();
+
+        assert_eq!(summary.follows, 2);
+        assert_eq!(summary.community_blocks, 1);
+        assert_eq!(summary.person_blocks, 1);
+        assert!(summary.avatar.unwrap().exists());
+        assert!(summary.banner.is_none());
+        let profile = LocalProfile::load("pull").unwrap().profile;
+        let follows: Vec<_> = profile.info.communities_follows.iter().map(|c| c.actor.to_string()).collect();
+        assert_eq!(follows, [format!("{}/c/rust", REMOTE), format!("{}/c/linux", REMOTE)]);
+        assert_eq!(profile.info.bio.as_deref(), Some("Hello"));
+    });
+}
+
+#[test]
+fn push_adds_missing_follows_and_blocks() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        pull(&source, "push").await;
+
+        let report = connect(&destination, "push").await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        assert!(report.is_complete());
+        assert_eq!(report.entries.iter().filter(|e| e.action == Action::Follow).count(), 2);
+        assert_eq!(destination.followed(), source.followed());
+        assert_eq!(destination.blocked_communities(), source.blocked_communities());
+        assert_eq!(destination.blocked_people(), source.blocked_people());
+        let settings = destination.state().saved_settings.clone().unwrap();
+        assert_eq!(settings["bio"], "Hello");
+        assert_eq!(destination.state().bio.as_deref(), Some("Hello"));
+    });
+}
+
+/// Destination reading password from environment variable `password_var`.
+fn remote(mock: &MockLemmy, password_var: &str, profile_name: &str) -> (Remote, Parameters) {
+    let state = mock.state();
+    let remote = Remote {
+        user: User::new(&state.username, &state.url),
+        password: Some(PasswordSource::Env(password_var.to_owned())),
+        profile_name: profile_name.to_owned(),
+        api: Api::new(),
+    };
+    (remote, Parameters::default())
+}
+
+#[test]
+fn push_many_reports_every_account() {
+    run(async {
+        let source = source_account();
+        let reachable = destination_account();
+        // Cached sessions are keyed by host only, so other username keeps it from resuming one.
+        let misconfigured = MockLemmy::start("carol", "other");
+        pull(&source, "push_many").await;
+        std::env::set_var("PUSH_MANY_PW", "other");
+        std::env::set_var("PUSH_MANY_WRONG_PW", "wrong");
+        let destinations = [remote(&reachable, "PUSH_MANY_PW", "push_many"), remote(&misconfigured, "PUSH_MANY_WRONG_PW", "push_many")];
+
+        let results = sync::push_many(&destinations, false, |_| {}).await;
+
+        assert_eq!(results.iter().filter(|r| r.report.as_ref().is_some_and(|r| r.is_complete())).count(), 1);
+        assert_eq!(results.iter().filter(|r| r.error.is_some()).count(), 1);
+        assert_eq!(reachable.followed(), source.followed());
+        assert!(misconfigured.followed().is_empty());
+        assert!(sync::check_pushed(&results).is_err());
+    });
+}
+
+#[test]
+fn push_keeps_extra_follows() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        destination.state().follows = vec![4];
+        pull(&source, "push_additive").await;
+
+        connect(&destination, "push_additive").await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        assert!(destination.followed().contains(&format!("{}/c/news", REMOTE)));
+        assert_eq!(destination.followed().len(), 3);
+    });
+}
+
+#[test]
+fn subtractive_push_removes_extra_follows_and_blocks() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        let extra_block = destination.add_person("friend", REMOTE);
+        {
+            let mut state = destination.state();
+            state.follows = vec![1, 4];
+            state.person_blocks = vec![extra_block];
+        }
+        pull(&source, "push_subtractive").await;
+
+        let report = connect(&destination, "push_subtractive").await
+            .push(true, &Parameters::default()).await
+            .unwrap();
+
+        assert!(report.is_complete());
+        assert_eq!(destination.followed(), source.followed());
+        assert_eq!(destination.blocked_people(), source.blocked_people());
+        let undone: Vec<_> = report.entries.iter().filter(|e| matches!(e.action, Action::Unfollow | Action::UnblockPerson)).collect();
+        assert_eq!(undone.len(), 2);
+    });
+}
+
+#[test]
+fn excluded_sections_are_left_untouched() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        destination.state().follows = vec![4];
+        pull(&source, "push_excluded").await;
+        let parameters = Parameters::new(&["follows".to_owned(), "settings".to_owned()], &[]).unwrap();
+
+        connect(&destination, "push_excluded").await
+            .push(true, &parameters).await
+            .unwrap();
+
+        assert_eq!(destination.followed(), [format!("{}/c/news", REMOTE)]);
+        assert_eq!(destination.blocked_communities(), source.blocked_communities());
+        assert!(destination.state().saved_settings.is_none());
+    });
+}
+
+#[test]
+fn unknown_community_is_reported_as_failed() {
+    run(async {
+        let source = source_account();
+        let destination = MockLemmy::start("alice", "other");
+        destination.add_community("rust", REMOTE);
+        pull(&source, "push_unknown").await;
+
+        let report = connect(&destination, "push_unknown").await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        assert!(!report.is_complete());
+        let failed: Vec<_> = report.failed().map(|e| e.target.clone()).collect();
+        assert!(failed.contains(&format!("{}/c/linux", REMOTE)));
+        assert_eq!(destination.followed(), [format!("{}/c/rust", REMOTE)]);
+    });
+}
+
+#[test]
+fn login_with_2fa_requires_token() {
+    run(async {
+        let mock = MockLemmy::start("bob", "secret");
+        mock.state().totp_token = Some("123456".to_owned());
+        let user = User::new("bob", &mock.url());
+
+        let result = Bliss::new(user.clone(), "secret".to_owned(), None, "2fa").await;
+        assert!(matches!(result, Err(Error::LoginError(_))));
+
+        let result = Bliss::new(user.clone(), "secret".to_owned(), Some("654321".to_owned()), "2fa").await;
+        assert!(matches!(result, Err(Error::LoginError(_))));
+
+        let bliss = Bliss::new(user, "secret".to_owned(), Some("123456".to_owned()), "2fa").await.unwrap();
+        assert_eq!(bliss.account(), format!("bob@{}", mock.url().host_str().unwrap()));
+    });
+}
+
+#[test]
+fn login_with_wrong_password_fails() {
+    run(async {
+        let mock = MockLemmy::start("carol", "secret");
+        let user = User::new("carol", &mock.url());
+
+        let result = Bliss::new(user, "wrong".to_owned(), None, "wrong_password").await;
+
+        assert!(matches!(result, Err(Error::LoginError(_))));
+    });
+}
+
+#[test]
+fn cached_session_is_resumed() {
+    run(async {
+        let mock = MockLemmy::start("dave", "secret");
+        let user = User::new("dave", &mock.url());
+        let bliss = Bliss::new(user.clone(), "secret".to_owned(), None, "session").await.unwrap();
+        assert!(Bliss::resume(user.clone(), "session").await.unwrap().is_none());
+
+        bliss.remember_session().unwrap();
+
+        assert!(Bliss::resume(user, "session").await.unwrap().is_some());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = home::home_dir().unwrap().join(".bliss/sessions");
+            assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+            for file in std::fs::read_dir(dir).unwrap() {
+                assert_eq!(file.unwrap().metadata().unwrap().permissions().mode() & 0o777, 0o600);
+            }
+        }
+    });
+}
+
+#[test]
+fn cached_session_survives_server_errors() {
+    run(async {
+        let mock = MockLemmy::start("erin", "secret");
+        let user = User::new("erin", &mock.url());
+        Bliss::new(user.clone(), "secret".to_owned(), None, "session_kept").await.unwrap()
+            .remember_session().unwrap();
+        mock.fail("/api/v3/site", 3, StatusCode::BAD_GATEWAY);
+
+        assert!(Bliss::resume(user.clone(), "session_kept").await.is_err());
+        assert!(Bliss::resume(user.clone(), "session_kept").await.unwrap().is_some());
+
+        mock.fail("/api/v3/site", 1, StatusCode::BAD_REQUEST);
+
+        assert!(Bliss::resume(user.clone(), "session_kept").await.unwrap().is_none());
+        assert!(Bliss::resume(user, "session_kept").await.unwrap().is_none());
+    });
+}
+
+#[test]
+fn push_uploads_included_avatar() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        pull(&source, "push_avatar").await;
+        let avatar = png([0, 0, 255]);
+        write_file(&profile_dir("push_avatar").join("avatar.png"), &avatar);
+        let parameters = Parameters::new(&[], &["avatar".to_owned()]).unwrap();
+
+        connect(&destination, "push_avatar").await
+            .push(false, &parameters).await
+            .unwrap();
+
+        let state = destination.state();
+        assert_eq!(state.images.values().collect::<Vec<_>>(), [&avatar]);
+        let uploaded = state.url.join("/pictrs/image/1.png").unwrap();
+        assert_eq!(state.avatar.as_deref(), Some(uploaded.as_str()));
+    });
+}
+
+#[test]
+fn pending_follows_are_reported_unconfirmed() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        destination.state().pending_follows = true;
+        pull(&source, "push_pending").await;
+
+        let report = connect(&destination, "push_pending").await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        let pending: Vec<_> = report.unconfirmed().map(|s| s.community.clone()).collect();
+        assert_eq!(pending, source.followed());
+    });
+}
+
+#[test]
+fn transient_failures_are_retried() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        pull(&source, "retry").await;
+        destination.fail("/api/v3/search", 2, StatusCode::BAD_GATEWAY);
+        destination.fail("/api/v3/community/follow", 1, StatusCode::SERVICE_UNAVAILABLE);
+
+        let report = connect_with(&destination, "retry", &fast_retries(3)).await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        assert!(report.is_complete());
+        assert_eq!(destination.followed(), source.followed());
+        assert_eq!(destination.requests("/api/v3/community/follow"), 3);
+    });
+}
+
+#[test]
+fn retries_stop_after_max_attempts() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        pull(&source, "retry_exhausted").await;
+        destination.fail("/api/v3/community/follow", 2, StatusCode::BAD_GATEWAY);
+
+        let report = connect_with(&destination, "retry_exhausted", &fast_retries(2)).await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        let failed: Vec<_> = report.failed().map(|e| e.target.clone()).collect();
+        assert_eq!(failed, [format!("{}/c/rust", REMOTE)]);
+        assert_eq!(destination.followed(), [format!("{}/c/linux", REMOTE)]);
+        assert_eq!(destination.requests("/api/v3/community/follow"), 3);
+    });
+}
+
+#[test]
+fn client_errors_are_not_retried() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        pull(&source, "retry_client_error").await;
+        destination.fail("/api/v3/community/follow", 1, StatusCode::BAD_REQUEST);
+
+        let report = connect_with(&destination, "retry_client_error", &fast_retries(3)).await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        assert_eq!(report.failed().count(), 1);
+        assert_eq!(destination.requests("/api/v3/community/follow"), 2);
+    });
+}
+
+#[test]
+fn image_upload_is_not_retried() {
+    run(async {
+        let source = source_account();
+        let destination = destination_account();
+        pull(&source, "retry_upload").await;
+        write_file(&profile_dir("retry_upload").join("avatar.png"), &png([0, 255, 0]));
+        destination.fail("/pictrs/image", 1, StatusCode::BAD_GATEWAY);
+        let parameters = Parameters::new(&[], &["avatar".to_owned()]).unwrap();
+
+        let result = connect_with(&destination, "retry_upload", &fast_retries(3)).await
+            .push(false, &parameters).await;
+
+        assert!(result.is_err());
+        assert_eq!(destination.requests("/pictrs/image"), 1);
+    });
+}
+
+/// Destination knowing `others` same named communities and people on other instances before the followed ones.
+fn crowded_destination(others: usize) -> MockLemmy {
+    let mock = MockLemmy::start("alice", "other");
+    for i in 0..others {
+        mock.add_community("linux", &format!("https://lemmy{}.example", i));
+        mock.add_person("troll", &format!("https://lemmy{}.example", i));
+    }
+    for name in ["rust", "linux", "memes"] {
+        mock.add_community(name, REMOTE);
+    }
+    mock.add_person("troll", REMOTE);
+    mock
+}
+
+#[test]
+fn common_names_resolve_by_actor_across_pages() {
+    run(async {
+        let source = source_account();
+        let destination = crowded_destination(120);
+        pull(&source, "search_pages").await;
+
+        let report = connect(&destination, "search_pages").await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        assert!(report.is_complete());
+        assert_eq!(destination.followed(), source.followed());
+        assert_eq!(destination.blocked_people(), source.blocked_people());
+    });
+}
+
+#[test]
+fn search_gives_up_after_page_cap() {
+    run(async {
+        let source = source_account();
+        let destination = crowded_destination(600);
+        pull(&source, "search_cap").await;
+
+        let report = connect(&destination, "search_cap").await
+            .push(false, &Parameters::new(&["community_blocks".to_owned(), "person_blocks".to_owned()], &[]).unwrap()).await
+            .unwrap();
+
+        let failed: Vec<_> = report.failed().map(|e| e.target.clone()).collect();
+        assert_eq!(failed, [format!("{}/c/linux", REMOTE)]);
+        assert_eq!(destination.followed(), [format!("{}/c/rust", REMOTE)]);
+        assert_eq!(destination.requests("/api/v3/search"), 11);
+    });
+}
+
+/// Instance hosting community `name` whose newest post is `days_ago` old, no posts if none.
+fn community_home(name: &str, days_ago: Option<i64>) -> MockLemmy {
+    let mock = MockLemmy::start("admin", "secret");
+    let host = host(&mock);
+    mock.add_community(name, &host);
+    if let Some(days) = days_ago {
+        let published = (Utc::now().naive_utc() - Duration::days(days)).format("%Y-%m-%dT%H:%M:%S").to_string();
+        mock.state().last_posts.insert(name.to_owned(), published);
+    }
+    mock
+}
+
+fn host(mock: &MockLemmy) -> String {
+    mock.url().as_str().trim_end_matches('/').to_owned()
+}
+
+#[test]
+fn audit_suggests_only_active_alternatives() {
+    run(async {
+        let dead = community_home("linux", None);
+        let active = community_home("linux", Some(1));
+        let stale = community_home("linux", Some(365));
+        let account = MockLemmy::start("alice", "secret");
+        let followed = account.add_community("linux", &host(&dead));
+        account.add_community("linux", &host(&stale));
+        account.add_community("linux", &host(&active));
+        account.add_community("linux", "http://127.0.0.1:1");
+        account.state().follows = vec![followed];
+        pull(&account, "audit").await;
+        let profile = LocalProfile::load("audit").unwrap().profile;
+
+        let audit = Audit::new(Api::with_config(&fast_retries(1)).unwrap(), 180, Some(account.url()));
+        let findings = audit.run(&profile).await;
+
+        assert_eq!(findings.len(), 1);
+        assert!(matches!(findings[0].cause, Cause::Inactive(None)));
+        let alternatives: Vec<_> = findings[0].alternatives.iter().map(|a| a.actor.clone()).collect();
+        assert_eq!(alternatives, [format!("{}/c/linux", host(&active))]);
+    });
+}
+
+fn tarball(path: &std::path::Path, files: &[(&str, &[u8])]) {
+    let encoder = flate2::write::GzEncoder::new(std::fs::File::create(path).unwrap(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, bytes) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *bytes).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn archive_with_unlisted_file_is_rejected() {
+    run(async {
+        let profile = b"profile".as_slice();
+        let sha256: String = Sha256::digest(profile).iter().map(|byte| format!("{:02x}", byte)).collect();
+        let manifest = format!("version: 1\nprofile_name: tampered\ndate_created: 2023-07-01T00:00:00+00:00\nfiles:\n\
+            - name: profile.yml\n  sha256: {0}\n- name: profile.yml\n  sha256: {0}\n", sha256);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tampered.tgz");
+        tarball(&path, &[("manifest.yml", manifest.as_bytes()), ("profile.yml", profile), ("avatar.png", &png([0, 0, 0]))]);
+
+        assert!(archive::import(&path, None, false).is_err());
+        assert!(!profile_dir("tampered").exists());
+    });
+}
+
+#[test]
+fn backup_images_are_not_pushed_unless_included() {
+    run(async {
+        let destination = destination_account();
+        destination.state().avatar = Some("https://example.org/own.png".to_owned());
+        let backup: UserSettingsBackup = serde_json::from_str(r#"{
+            "avatar": "https://lemmy.ml/pictrs/image/foreign.png",
+            "banner": "https://lemmy.ml/pictrs/image/foreign_banner.png"
+        }"#).unwrap();
+        let user = User::new("alice", &destination.url());
+        LocalProfile::new("from_backup", Profile::from_backup(user, &backup)).save().unwrap();
+
+        connect(&destination, "from_backup").await
+            .push(false, &Parameters::default()).await
+            .unwrap();
+
+        let settings = destination.state().saved_settings.clone().unwrap();
+        assert!(settings["avatar"].is_null() && settings["banner"].is_null());
+        assert_eq!(destination.state().avatar.as_deref(), Some("https://example.org/own.png"));
+    });
+}
+
+#[test]
+fn audit_skips_stale_pinned_post() {
+    run(async {
+        let home = community_home("linux", Some(1));
+        home.state().pinned_posts.insert("linux".to_owned(), "2020-01-01T00:00:00".to_owned());
+        let account = MockLemmy::start("alice", "secret");
+        let followed = account.add_community("linux", &host(&home));
+        account.state().follows = vec![followed];
+        pull(&account, "audit_pinned").await;
+        let profile = LocalProfile::load("audit_pinned").unwrap().profile;
+
+        let findings = Audit::new(Api::new(), 180, None).run(&profile).await;
+
+        assert!(findings.is_empty());
+    });
+}
+
+#[test]
+fn encryption_covers_sync_base() {
+    run(async {
+        let user = User::new("alice", &"https://lemmy.ml".parse().unwrap());
+        let mut lp = LocalProfile::new("encrypted_base", Profile::from_backup(user, &UserSettingsBackup::default()));
+        lp.save().unwrap();
+        lp.save_sync_base(&lp.profile).unwrap();
+        let base = profile_dir("encrypted_base").join("sync_base.yml");
+
+        lp.change_passphrase(Some("passphrase".to_owned())).unwrap();
+
+        assert!(serde_yaml::from_slice::<Profile>(&std::fs::read(&base).unwrap()).is_err());
+
+        lp.change_passphrase(None).unwrap();
+
+        assert!(serde_yaml::from_slice::<Profile>(&std::fs::read(&base).unwrap()).is_ok());
+    });
+}
+
+#[test]
+fn profile_opens_with_given_passphrase() {
+    run(async {
+        let user = User::new("alice", &"https://lemmy.ml".parse().unwrap());
+        let mut lp = LocalProfile::new("given_passphrase", Profile::from_backup(user, &UserSettingsBackup::default()));
+        lp.change_passphrase(Some("passphrase".to_owned())).unwrap();
+
+        let loaded = LocalProfile::load_with("given_passphrase", Some("passphrase")).unwrap();
+
+        assert_eq!(loaded.profile.user().username, "alice");
+        assert!(LocalProfile::load_with("given_passphrase", Some("wrong")).is_err());
+    });
+}
+
+#[test]
+fn malformed_profile_is_error() {
+    run(async {
+        write_file(&profile_dir("malformed").join("profile.yml"), b"user: [");
+
+        assert!(LocalProfile::load("malformed").is_err());
+    });
+}
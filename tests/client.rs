@@ -0,0 +1,121 @@
+use std::{net::TcpListener, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use axum::{Router, routing::get, response::IntoResponse, http::{HeaderMap, StatusCode, header::RETRY_AFTER}};
+use bliss::{lemmy::{api::Api, client::{ClientConfig, default_user_agent}}, user::User};
+use url::Url;
+
+#[tokio::test]
+async fn request_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    let config = ClientConfig {
+        timeout: Some(1),
+        max_attempts: Some(1),
+        ..Default::default()
+    };
+    let api = Api::with_config(&config).unwrap();
+
+    let start = Instant::now();
+    let result = api.community(&url, "linux").await;
+
+    assert!(result.is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    drop(listener);
+}
+
+#[tokio::test]
+async fn error_does_not_leak_token() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    drop(listener);
+    let user = User::new("alice", &url).authorize("secret-jwt".to_owned());
+    let config = ClientConfig {
+        max_attempts: Some(1),
+        ..Default::default()
+    };
+
+    let err = Api::with_config(&config).unwrap().site(&user).await.unwrap_err();
+
+    assert!(!err.to_string().contains("secret-jwt"), "{}", err);
+}
+
+async fn user_agent_seen(config: &ClientConfig) -> Option<String> {
+    let seen = Arc::new(Mutex::new(None));
+    let app = Router::new().route("/api/v3/community", get({
+        let seen = seen.clone();
+        move |headers: HeaderMap| async move {
+            *seen.lock().unwrap() = headers.get("user-agent").map(|ua| ua.to_str().unwrap().to_owned());
+            StatusCode::NOT_FOUND
+        }
+    }));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+    let result = Api::with_config(config).unwrap().community(&url, "linux").await;
+
+    assert!(result.is_err());
+    let ua = seen.lock().unwrap().clone();
+    ua
+}
+
+#[tokio::test]
+async fn user_agent_includes_version() {
+    let ua = user_agent_seen(&ClientConfig::default()).await.unwrap();
+
+    assert_eq!(ua, default_user_agent());
+    assert!(ua.starts_with(&format!("bliss/{}", env!("CARGO_PKG_VERSION"))));
+}
+
+#[tokio::test]
+async fn user_agent_can_be_replaced() {
+    let config = ClientConfig {
+        user_agent: Some("mirror-bot/1.0".to_owned()),
+        ..Default::default()
+    };
+
+    assert_eq!(user_agent_seen(&config).await.as_deref(), Some("mirror-bot/1.0"));
+}
+
+#[test]
+fn invalid_certificate_is_rejected() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), "not a certificate").unwrap();
+    let config = ClientConfig {
+        ca_certificates: vec![file.path().to_owned()],
+        ..Default::default()
+    };
+
+    assert!(Api::with_config(&config).is_err());
+}
+
+#[tokio::test]
+async fn retry_waits_as_long_as_server_asks() {
+    let calls = Arc::new(Mutex::new(0));
+    let app = Router::new().route("/api/v3/community", get({
+        let calls = calls.clone();
+        move || async move {
+            let mut calls = calls.lock().unwrap();
+            *calls += 1;
+            match *calls {
+                1 => (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, "1")]).into_response(),
+                _ => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+    }));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+    let config = ClientConfig {
+        max_attempts: Some(2),
+        retry_delay: Some(60_000),
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let result = Api::with_config(&config).unwrap().community(&url, "linux").await;
+
+    assert!(result.is_err());
+    assert_eq!(*calls.lock().unwrap(), 2);
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
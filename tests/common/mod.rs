@@ -0,0 +1,651 @@
+//! In-process mock of Lemmy HTTP API, serving single account whose state tests can script and inspect.
+
+use std::{collections::HashMap, net::TcpListener, path::Path, sync::{Arc, Mutex, MutexGuard, Once}};
+use axum::{Router, Json, routing::{get, post, put}, extract::{State, Query, Multipart, Path as UrlPath}, http::{Request, StatusCode, HeaderMap}, middleware::{self, Next}, response::{IntoResponse, Response}};
+use serde_json::{json, Value};
+use url::Url;
+
+const JWT: &str = "mock-jwt";
+const DATE: &str = "2023-07-01T00:00:00";
+
+/// Points home directory, where profiles and sessions are stored, to directory in cargo's target shared by all tests
+/// and emptied by first one. Tests keep apart by using different profile names and their own server.
+fn init_home() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("home");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("HOME", dir);
+    });
+}
+
+/// Runs test on its own runtime, started only after home directory is set, so no other thread reads environment meanwhile.
+pub fn run<F: std::future::Future>(test: F) -> F::Output {
+    init_home();
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(test)
+}
+
+/// Community or person known to mock instance.
+#[derive(Clone)]
+pub struct Actor {
+    pub id: i32,
+    pub name: String,
+    pub actor: String,
+}
+
+/// Scriptable state of mock instance.
+pub struct MockState {
+    pub url: Url,
+    pub username: String,
+    pub password: String,
+    /// Login fails unless this token is given.
+    pub totp_token: Option<String>,
+    pub bio: Option<String>,
+    pub display_name: Option<String>,
+    pub avatar: Option<String>,
+    pub communities: Vec<Actor>,
+    pub people: Vec<Actor>,
+    /// Ids of followed and blocked communities and blocked people.
+    pub follows: Vec<i32>,
+    pub community_blocks: Vec<i32>,
+    pub person_blocks: Vec<i32>,
+    /// Follows of remote communities answer pending instead of subscribed.
+    pub pending_follows: bool,
+    /// Last body of save_user_settings.
+    pub saved_settings: Option<Value>,
+    /// Uploaded images by file name, served back by pictrs endpoint.
+    pub images: HashMap<String, Vec<u8>>,
+    /// Number of next requests to path, e.g. `/api/v3/search`, answered with given status before handling it.
+    pub failures: HashMap<String, (u32, StatusCode)>,
+    /// Number of requests received per path, including failed ones.
+    pub requests: HashMap<String, u32>,
+    /// Publication date of newest post by community name, communities not listed have no posts.
+    pub last_posts: HashMap<String, String>,
+    /// Publication date of post pinned in community by its name, listed before other posts like Lemmy does.
+    pub pinned_posts: HashMap<String, String>,
+}
+
+impl MockState {
+    fn community(&self, id: i32) -> Option<&Actor> {
+        self.communities.iter().find(|c| c.id == id)
+    }
+
+    fn person(&self, id: i32) -> Option<&Actor> {
+        self.people.iter().find(|p| p.id == id)
+    }
+
+    fn host(&self) -> String {
+        self.url.as_str().trim_end_matches('/').to_owned()
+    }
+}
+
+type Shared = Arc<Mutex<MockState>>;
+
+pub struct MockLemmy {
+    state: Shared,
+}
+
+impl MockLemmy {
+    /// Starts server on random port, with account `username`/`password` and no follows or blocks.
+    pub fn start(username: &str, password: &str) -> MockLemmy {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let state = Arc::new(Mutex::new(MockState {
+            url,
+            username: username.to_owned(),
+            password: password.to_owned(),
+            totp_token: None,
+            bio: None,
+            display_name: None,
+            avatar: None,
+            communities: Vec::new(),
+            people: Vec::new(),
+            follows: Vec::new(),
+            community_blocks: Vec::new(),
+            person_blocks: Vec::new(),
+            pending_follows: false,
+            saved_settings: None,
+            images: HashMap::new(),
+            failures: HashMap::new(),
+            requests: HashMap::new(),
+            last_posts: HashMap::new(),
+            pinned_posts: HashMap::new(),
+        }));
+        let app = Router::new()
+            .route("/api/v3/user/login", post(login))
+            .route("/api/v3/site", get(site))
+            .route("/api/v3/federated_instances", get(federated_instances))
+            .route("/api/v3/search", get(search))
+            .route("/api/v3/community", get(community))
+            .route("/api/v3/post/list", get(posts))
+            .route("/api/v3/community/follow", post(follow))
+            .route("/api/v3/community/block", post(block_community))
+            .route("/api/v3/user/block", post(block_person))
+            .route("/api/v3/user/save_user_settings", put(save_user_settings))
+            .route("/pictrs/image", post(upload_image))
+            .route("/pictrs/image/:file", get(image))
+            .layer(middleware::from_fn_with_state(state.clone(), inject_failures))
+            .with_state(state.clone());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        MockLemmy { state }
+    }
+
+    pub fn url(&self) -> Url {
+        self.state().url.clone()
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Makes next `count` requests to `path` fail with `status`.
+    pub fn fail(&self, path: &str, count: u32, status: StatusCode) {
+        self.state().failures.insert(path.to_owned(), (count, status));
+    }
+
+    pub fn requests(&self, path: &str) -> u32 {
+        self.state().requests.get(path).copied().unwrap_or_default()
+    }
+
+    /// Makes community `name` hosted on `instance` known to this instance, returns its id.
+    pub fn add_community(&self, name: &str, instance: &str) -> i32 {
+        let mut state = self.state();
+        let id = state.communities.len() as i32 + 1;
+        state.communities.push(Actor { id, name: name.to_owned(), actor: format!("{}/c/{}", instance, name) });
+        id
+    }
+
+    /// Makes person `name` hosted on `instance` known to this instance, returns its id.
+    pub fn add_person(&self, name: &str, instance: &str) -> i32 {
+        let mut state = self.state();
+        let id = state.people.len() as i32 + 100;
+        state.people.push(Actor { id, name: name.to_owned(), actor: format!("{}/u/{}", instance, name) });
+        id
+    }
+
+    /// Actor urls of followed communities.
+    pub fn followed(&self) -> Vec<String> {
+        let state = self.state();
+        state.follows.iter().filter_map(|id| state.community(*id)).map(|c| c.actor.clone()).collect()
+    }
+
+    pub fn blocked_communities(&self) -> Vec<String> {
+        let state = self.state();
+        state.community_blocks.iter().filter_map(|id| state.community(*id)).map(|c| c.actor.clone()).collect()
+    }
+
+    pub fn blocked_people(&self) -> Vec<String> {
+        let state = self.state();
+        state.person_blocks.iter().filter_map(|id| state.person(*id)).map(|p| p.actor.clone()).collect()
+    }
+}
+
+/// Valid PNG image of given color.
+pub fn png(color: [u8; 3]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image::RgbImage::from_pixel(2, 2, image::Rgb(color))
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+        .unwrap();
+    bytes
+}
+
+pub fn profile_dir(profile_name: &str) -> std::path::PathBuf {
+    home::home_dir().unwrap().join(".bliss/profiles").join(profile_name)
+}
+
+pub fn write_file(path: &Path, bytes: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, bytes).unwrap();
+}
+
+async fn inject_failures<B>(State(state): State<Shared>, request: Request<B>, next: Next<B>) -> Response {
+    let failure = {
+        let mut state = state.lock().unwrap();
+        let path = request.uri().path().to_owned();
+        *state.requests.entry(path.clone()).or_default() += 1;
+        match state.failures.get_mut(&path) {
+            Some((count, status)) if *count > 0 => {
+                *count -= 1;
+                Some(*status)
+            },
+            _ => None,
+        }
+    };
+    match failure {
+        Some(status) => error(status, "injected_failure"),
+        None => next.run(request).await,
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+fn authorized(auth: Option<&str>) -> bool {
+    auth == Some(JWT)
+}
+
+fn not_logged_in() -> Response {
+    error(StatusCode::BAD_REQUEST, "not_logged_in")
+}
+
+fn body_auth(body: &Value) -> Option<&str> {
+    body.get("auth").and_then(Value::as_str)
+}
+
+fn person_json(id: i32, name: &str, actor: &str) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "banned": false,
+        "published": DATE,
+        "actor_id": actor,
+        "local": false,
+        "deleted": false,
+        "admin": false,
+        "bot_account": false,
+        "instance_id": 1,
+    })
+}
+
+fn person_view_json(person: Value) -> Value {
+    let id = person["id"].clone();
+    json!({
+        "person": person,
+        "counts": { "id": id, "person_id": id, "post_count": 0, "post_score": 0, "comment_count": 0, "comment_score": 0 },
+    })
+}
+
+fn community_json(community: &Actor) -> Value {
+    json!({
+        "id": community.id,
+        "name": community.name,
+        "title": community.name,
+        "removed": false,
+        "published": DATE,
+        "deleted": false,
+        "nsfw": false,
+        "actor_id": community.actor,
+        "local": false,
+        "hidden": false,
+        "posting_restricted_to_mods": false,
+        "instance_id": 1,
+    })
+}
+
+fn community_view_json(community: &Actor, subscribed: &str, blocked: bool) -> Value {
+    json!({
+        "community": community_json(community),
+        "subscribed": subscribed,
+        "blocked": blocked,
+        "counts": {
+            "id": community.id,
+            "community_id": community.id,
+            "subscribers": 1,
+            "posts": 0,
+            "comments": 0,
+            "published": DATE,
+            "users_active_day": 0,
+            "users_active_week": 0,
+            "users_active_month": 0,
+            "users_active_half_year": 0,
+            "hot_rank": 0,
+        },
+    })
+}
+
+fn me_json(state: &MockState) -> Value {
+    let mut me = person_json(1, &state.username, &format!("{}/u/{}", state.host(), state.username));
+    me["local"] = json!(true);
+    me["bio"] = json!(state.bio);
+    me["display_name"] = json!(state.display_name);
+    me["avatar"] = json!(state.avatar);
+    me
+}
+
+async fn login(State(state): State<Shared>, Json(body): Json<Value>) -> Response {
+    let state = state.lock().unwrap();
+    if body["username_or_email"] != state.username || body["password"] != state.password {
+        return error(StatusCode::BAD_REQUEST, "incorrect_login");
+    }
+    if let Some(token) = &state.totp_token {
+        if body["totp_2fa_token"].as_str() != Some(token) {
+            return error(StatusCode::BAD_REQUEST, "missing_totp_token");
+        }
+    }
+    Json(json!({ "jwt": JWT, "registration_created": false, "verify_email_sent": false })).into_response()
+}
+
+async fn site(State(state): State<Shared>, Query(query): Query<HashMap<String, String>>) -> Response {
+    if !authorized(query.get("auth").map(String::as_str)) {
+        return not_logged_in();
+    }
+    let state = state.lock().unwrap();
+    let me = me_json(&state);
+    let follows: Vec<_> = state.follows
+        .iter()
+        .filter_map(|id| state.community(*id))
+        .map(|c| json!({ "community": community_json(c), "follower": me }))
+        .collect();
+    let community_blocks: Vec<_> = state.community_blocks
+        .iter()
+        .filter_map(|id| state.community(*id))
+        .map(|c| json!({ "person": me, "community": community_json(c) }))
+        .collect();
+    let person_blocks: Vec<_> = state.person_blocks
+        .iter()
+        .filter_map(|id| state.person(*id))
+        .map(|p| json!({ "person": me, "target": person_json(p.id, &p.name, &p.actor) }))
+        .collect();
+    Json(json!({
+        "site_view": {
+            "site": {
+                "id": 1,
+                "name": "mock",
+                "published": DATE,
+                "actor_id": state.host(),
+                "last_refreshed_at": DATE,
+                "inbox_url": format!("{}/inbox", state.host()),
+                "public_key": "",
+                "instance_id": 1,
+            },
+            "local_site": {
+                "id": 1,
+                "site_id": 1,
+                "site_setup": true,
+                "enable_downvotes": true,
+                "enable_nsfw": true,
+                "community_creation_admin_only": false,
+                "require_email_verification": false,
+                "private_instance": false,
+                "default_theme": "browser",
+                "default_post_listing_type": "Local",
+                "hide_modlog_mod_names": true,
+                "application_email_admins": false,
+                "actor_name_max_length": 20,
+                "federation_enabled": true,
+                "captcha_enabled": false,
+                "captcha_difficulty": "medium",
+                "published": DATE,
+                "registration_mode": "Open",
+                "reports_email_admins": false,
+            },
+            "local_site_rate_limit": {
+                "id": 1,
+                "local_site_id": 1,
+                "message": 1000,
+                "message_per_second": 1000,
+                "post": 1000,
+                "post_per_second": 1000,
+                "register": 1000,
+                "register_per_second": 1000,
+                "image": 1000,
+                "image_per_second": 1000,
+                "comment": 1000,
+                "comment_per_second": 1000,
+                "search": 1000,
+                "search_per_second": 1000,
+                "published": DATE,
+            },
+            "counts": {
+                "id": 1,
+                "site_id": 1,
+                "users": 1,
+                "posts": 0,
+                "comments": 0,
+                "communities": state.communities.len(),
+                "users_active_day": 0,
+                "users_active_week": 0,
+                "users_active_month": 0,
+                "users_active_half_year": 0,
+            },
+        },
+        "admins": [],
+        "version": "0.18.1",
+        "my_user": {
+            "local_user_view": {
+                "local_user": {
+                    "id": 1,
+                    "person_id": 1,
+                    "show_nsfw": false,
+                    "theme": "browser",
+                    "default_sort_type": "Active",
+                    "default_listing_type": "Local",
+                    "interface_language": "en",
+                    "show_avatars": true,
+                    "send_notifications_to_email": false,
+                    "validator_time": DATE,
+                    "show_scores": true,
+                    "show_bot_accounts": true,
+                    "show_read_posts": true,
+                    "show_new_post_notifs": false,
+                    "email_verified": false,
+                    "accepted_application": true,
+                    "open_links_in_new_tab": false,
+                },
+                "person": me,
+                "counts": person_view_json(me.clone())["counts"],
+            },
+            "follows": follows,
+            "moderates": [],
+            "community_blocks": community_blocks,
+            "person_blocks": person_blocks,
+            "discussion_languages": [],
+        },
+        "all_languages": [],
+        "discussion_languages": [],
+        "taglines": [],
+        "custom_emojis": [],
+    })).into_response()
+}
+
+async fn federated_instances(Query(query): Query<HashMap<String, String>>) -> Response {
+    if !authorized(query.get("auth").map(String::as_str)) {
+        return not_logged_in();
+    }
+    Json(json!({ "federated_instances": null })).into_response()
+}
+
+/// Like Lemmy, matches names containing query and returns requested page, 10 results by default.
+async fn search(State(state): State<Shared>, Query(query): Query<HashMap<String, String>>) -> Response {
+    let state = state.lock().unwrap();
+    let q = query.get("q").cloned().unwrap_or_default();
+    let type_ = query.get("type_").cloned().unwrap_or_default();
+    let page: usize = query.get("page").and_then(|p| p.parse().ok()).unwrap_or(1);
+    let limit: usize = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(10);
+    let skip = (page.max(1) - 1) * limit;
+    let communities: Vec<_> = state.communities
+        .iter()
+        .filter(|c| type_ == "Communities" && c.name.contains(&q))
+        .skip(skip)
+        .take(limit)
+        .map(|c| community_view_json(c, "NotSubscribed", false))
+        .collect();
+    let users: Vec<_> = state.people
+        .iter()
+        .filter(|p| type_ == "Users" && p.name.contains(&q))
+        .skip(skip)
+        .take(limit)
+        .map(|p| person_view_json(person_json(p.id, &p.name, &p.actor)))
+        .collect();
+    Json(json!({ "type_": type_, "comments": [], "posts": [], "communities": communities, "users": users })).into_response()
+}
+
+async fn community(State(state): State<Shared>, Query(query): Query<HashMap<String, String>>) -> Response {
+    let state = state.lock().unwrap();
+    let name = query.get("name").cloned().unwrap_or_default();
+    match state.communities.iter().find(|c| c.name == name) {
+        Some(community) => Json(json!({
+            "community_view": community_view_json(community, "NotSubscribed", false),
+            "moderators": [],
+            "discussion_languages": [],
+        })).into_response(),
+        None => error(StatusCode::NOT_FOUND, "couldnt_find_community"),
+    }
+}
+
+/// Pinned post of community followed by its newest one, as it is all audit asks for.
+async fn posts(State(state): State<Shared>, Query(query): Query<HashMap<String, String>>) -> Response {
+    let state = state.lock().unwrap();
+    let name = query.get("community_name").cloned().unwrap_or_default();
+    let community = match state.communities.iter().find(|c| c.name == name) {
+        Some(community) => community,
+        None => return error(StatusCode::NOT_FOUND, "couldnt_find_community"),
+    };
+    let limit: usize = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(10);
+    let pinned = state.pinned_posts.get(&name).map(|published| post_view_json(community, 1, published, true));
+    let newest = state.last_posts.get(&name).map(|published| post_view_json(community, 2, published, false));
+    let posts: Vec<_> = pinned.into_iter().chain(newest).take(limit).collect();
+    Json(json!({ "posts": posts })).into_response()
+}
+
+fn post_view_json(community: &Actor, id: i32, published: &str, featured: bool) -> Value {
+    json!({
+        "post": {
+            "id": id,
+            "name": "Post",
+            "creator_id": 1,
+            "community_id": community.id,
+            "removed": false,
+            "locked": false,
+            "published": published,
+            "deleted": false,
+            "nsfw": false,
+            "ap_id": format!("{}/post/{}", community.actor, id),
+            "local": false,
+            "language_id": 0,
+            "featured_community": featured,
+            "featured_local": false,
+        },
+        "creator": person_json(1, "author", &format!("{}/u/author", community.actor)),
+        "community": community_json(community),
+        "creator_banned_from_community": false,
+        "counts": {
+            "id": id,
+            "post_id": id,
+            "comments": 0,
+            "score": 0,
+            "upvotes": 0,
+            "downvotes": 0,
+            "published": published,
+            "newest_comment_time_necro": published,
+            "newest_comment_time": published,
+            "featured_community": featured,
+            "featured_local": false,
+            "hot_rank": 0,
+            "hot_rank_active": 0,
+        },
+        "subscribed": "NotSubscribed",
+        "saved": false,
+        "read": false,
+        "creator_blocked": false,
+        "unread_comments": 0,
+    })
+}
+
+async fn follow(State(state): State<Shared>, Json(body): Json<Value>) -> Response {
+    if !authorized(body_auth(&body)) {
+        return not_logged_in();
+    }
+    let mut state = state.lock().unwrap();
+    let id = body["community_id"].as_i64().unwrap_or_default() as i32;
+    let community = match state.community(id) {
+        Some(community) => community.clone(),
+        None => return error(StatusCode::BAD_REQUEST, "couldnt_find_community"),
+    };
+    state.follows.retain(|f| *f != id);
+    let subscribed = match body["follow"].as_bool() {
+        Some(true) => {
+            state.follows.push(id);
+            if state.pending_follows { "Pending" } else { "Subscribed" }
+        },
+        _ => "NotSubscribed",
+    };
+    Json(json!({ "community_view": community_view_json(&community, subscribed, false), "discussion_languages": [] })).into_response()
+}
+
+async fn block_community(State(state): State<Shared>, Json(body): Json<Value>) -> Response {
+    if !authorized(body_auth(&body)) {
+        return not_logged_in();
+    }
+    let mut state = state.lock().unwrap();
+    let id = body["community_id"].as_i64().unwrap_or_default() as i32;
+    let community = match state.community(id) {
+        Some(community) => community.clone(),
+        None => return error(StatusCode::BAD_REQUEST, "couldnt_find_community"),
+    };
+    let blocked = body["block"].as_bool().unwrap_or_default();
+    state.community_blocks.retain(|b| *b != id);
+    if blocked {
+        state.community_blocks.push(id);
+    }
+    Json(json!({ "community_view": community_view_json(&community, "NotSubscribed", blocked), "blocked": blocked })).into_response()
+}
+
+async fn block_person(State(state): State<Shared>, Json(body): Json<Value>) -> Response {
+    if !authorized(body_auth(&body)) {
+        return not_logged_in();
+    }
+    let mut state = state.lock().unwrap();
+    let id = body["person_id"].as_i64().unwrap_or_default() as i32;
+    let person = match state.person(id) {
+        Some(person) => person.clone(),
+        None => return error(StatusCode::BAD_REQUEST, "couldnt_find_person"),
+    };
+    let blocked = body["block"].as_bool().unwrap_or_default();
+    state.person_blocks.retain(|b| *b != id);
+    if blocked {
+        state.person_blocks.push(id);
+    }
+    Json(json!({ "person_view": person_view_json(person_json(person.id, &person.name, &person.actor)), "blocked": blocked })).into_response()
+}
+
+async fn save_user_settings(State(state): State<Shared>, Json(body): Json<Value>) -> Response {
+    if !authorized(body_auth(&body)) {
+        return not_logged_in();
+    }
+    let mut state = state.lock().unwrap();
+    state.bio = body["bio"].as_str().map(str::to_owned);
+    state.display_name = body["display_name"].as_str().map(str::to_owned);
+    if let Some(avatar) = body["avatar"].as_str() {
+        state.avatar = Some(avatar.to_owned());
+    }
+    state.saved_settings = Some(body);
+    Json(json!({ "jwt": JWT, "registration_created": false, "verify_email_sent": false })).into_response()
+}
+
+async fn upload_image(State(state): State<Shared>, headers: HeaderMap, mut multipart: Multipart) -> Response {
+    let cookie = headers.get("cookie").and_then(|c| c.to_str().ok());
+    if !authorized(cookie.and_then(|c| c.strip_prefix("jwt="))) {
+        return not_logged_in();
+    }
+    let mut uploads = Vec::new();
+    while let Ok(Some(field)) = multipart.next_field().await {
+        uploads.push(field.bytes().await.unwrap().to_vec());
+    }
+    let mut state = state.lock().unwrap();
+    let mut files = Vec::new();
+    for bytes in uploads {
+        let file = format!("{}.png", state.images.len() + 1);
+        state.images.insert(file.clone(), bytes);
+        files.push(json!({ "file": file, "delete_token": "token" }));
+    }
+    Json(json!({ "msg": "ok", "files": files })).into_response()
+}
+
+async fn image(State(state): State<Shared>, UrlPath(file): UrlPath<String>) -> Response {
+    match state.lock().unwrap().images.get(&file) {
+        Some(bytes) => bytes.clone().into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
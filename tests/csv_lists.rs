@@ -0,0 +1,55 @@
+use bliss::profile::{Info, community::Community, csv_lists::{self, Section}};
+use lemmy_api_common::lemmy_db_schema::newtypes::{CommunityId, DbUrl};
+use url::Url;
+
+fn actor(url: &str) -> DbUrl {
+    Url::parse(url).unwrap().into()
+}
+
+fn info(follows: Vec<Community>) -> Info {
+    Info {
+        communities_blocks: Vec::new(),
+        communities_follows: follows,
+        people_blocks: Vec::new(),
+        bio: None,
+        display_name: None,
+        avatar: None,
+        banner: None,
+    }
+}
+
+fn pulled(url: &str, id: i32) -> Community {
+    Community {
+        id: CommunityId(id),
+        ..Community::from_actor(actor(url))
+    }
+}
+
+const CSV: &[u8] = b"name,actor\n\
+    Linux fans,https://lemmy.ml/c/linux\n\
+    ,https://beehaw.org/c/technology\n\
+    again,https://beehaw.org/c/technology\n";
+
+#[test]
+fn import_matches_entries_by_actor() {
+    let mut info = info(vec![pulled("https://lemmy.ml/c/linux", 7)]);
+
+    let added = csv_lists::import(&mut info, Section::Follows, CSV, false).unwrap();
+
+    assert_eq!(added, 1);
+    let follows = &info.communities_follows;
+    assert_eq!(follows.len(), 2);
+    assert_eq!((follows[0].name.as_str(), follows[0].id), ("linux", CommunityId(7)));
+    assert_eq!((follows[1].name.as_str(), follows[1].actor.clone()), ("technology", actor("https://beehaw.org/c/technology")));
+}
+
+#[test]
+fn replace_keeps_pulled_entries_listed_in_file() {
+    let mut info = info(vec![pulled("https://lemmy.ml/c/linux", 7), pulled("https://lemmy.ml/c/rust", 8)]);
+
+    let added = csv_lists::import(&mut info, Section::Follows, CSV, true).unwrap();
+
+    assert_eq!(added, 1);
+    let ids: Vec<CommunityId> = info.communities_follows.iter().map(|c| c.id).collect();
+    assert_eq!(ids, vec![CommunityId(7), CommunityId::default()]);
+}
@@ -0,0 +1,24 @@
+use bliss::{User, profile::{Profile, lemmy_backup::UserSettingsBackup}};
+use url::Url;
+
+const BACKUP: &str = r#"{
+    "display_name": "Alice",
+    "avatar": "https://lemmy.ml/pictrs/image/avatar.png",
+    "banner": "https://lemmy.ml/pictrs/image/banner.png",
+    "followed_communities": ["https://lemmy.ml/c/linux"]
+}"#;
+
+#[test]
+fn images_survive_round_trip() {
+    let backup: UserSettingsBackup = serde_json::from_str(BACKUP).unwrap();
+    let user = User::new("alice", &Url::parse("https://lemmy.ml").unwrap());
+
+    let profile = Profile::from_backup(user, &backup);
+
+    assert_eq!(profile.info.avatar.as_deref(), Some("https://lemmy.ml/pictrs/image/avatar.png"));
+    assert_eq!(profile.info.banner.as_deref(), Some("https://lemmy.ml/pictrs/image/banner.png"));
+    let exported = serde_json::to_value(UserSettingsBackup::from(&profile)).unwrap();
+    let original: serde_json::Value = serde_json::from_str(BACKUP).unwrap();
+    assert_eq!(exported["avatar"], original["avatar"]);
+    assert_eq!(exported["banner"], original["banner"]);
+}